@@ -46,6 +46,7 @@
               libGL
               libGLU
               mesa
+              pixman
             ];
           };
     in
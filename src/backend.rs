@@ -1,10 +1,185 @@
-use smithay::reexports::wayland_server::DisplayHandle;
+use std::{error::Error, fmt, path::PathBuf};
+
+use smithay::{
+    output::Scale,
+    reexports::{calloop::EventLoop, wayland_server::DisplayHandle},
+};
+use tracing::{info, warn};
 
 use crate::state::ThingState;
 
+pub mod headless;
+pub mod scheduler;
 pub mod winit;
+pub mod x11;
 
 pub struct CalloopData {
     pub state: ThingState,
     pub dh: DisplayHandle,
 }
+
+/// Options shared by the backends.
+///
+/// The size, scale and transform of the outputs come from [`ThingState::output_config`].
+#[derive(Debug, Clone)]
+pub struct BackendOptions {
+    /// Number of outputs to create, on backends supporting more than one
+    pub output_count: usize,
+    /// Directory where the headless backend writes every frame it renders, see
+    /// [`headless::run`]
+    pub dump_frames: Option<PathBuf>,
+}
+
+impl Default for BackendOptions {
+    fn default() -> Self {
+        Self {
+            output_count: 1,
+            dump_frames: None,
+        }
+    }
+}
+
+/// Converts a scale factor to an output scale, using an integer scale when possible
+pub fn output_scale(scale: f64) -> Scale {
+    if scale.fract() == 0.0 {
+        Scale::Integer(scale as i32)
+    } else {
+        Scale::Fractional(scale)
+    }
+}
+
+/// Environment variable used to force a backend
+pub const BACKEND_ENV: &str = "THING_BACKEND";
+
+/// The backends the compositor can run on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// Nested in a window of an existing Wayland or X11 session
+    Winit,
+    /// Nested in a window of an existing X11 session
+    X11,
+    /// Without any display, rendering on the CPU
+    Headless,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 3] = [BackendKind::Winit, BackendKind::X11, BackendKind::Headless];
+
+    /// Name used to select this backend
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Winit => "winit",
+            BackendKind::X11 => "x11",
+            BackendKind::Headless => "headless",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Backends usable in the current environment, from the most to the least suitable
+    pub fn detect() -> Vec<Self> {
+        let has_env = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+
+        match (has_env("WAYLAND_DISPLAY"), has_env("DISPLAY")) {
+            (true, true) => vec![BackendKind::Winit, BackendKind::X11],
+            (true, false) => vec![BackendKind::Winit],
+            (false, true) => vec![BackendKind::X11, BackendKind::Winit],
+            (false, false) => vec![BackendKind::Headless],
+        }
+    }
+
+    fn start(
+        &self,
+        event_loop: &mut EventLoop<CalloopData>,
+        data: &mut CalloopData,
+        options: &BackendOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        match self {
+            BackendKind::Winit => winit::run(event_loop, data, options),
+            BackendKind::X11 => x11::run(event_loop, data, options),
+            BackendKind::Headless => headless::run(event_loop, data, options),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Error returned when no backend could be started
+#[derive(Debug)]
+pub enum BackendError {
+    /// The requested backend does not exist
+    Unknown(String),
+    /// Every candidate backend failed to start
+    Failed(Vec<(BackendKind, Box<dyn Error>)>),
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Unknown(name) => {
+                let names: Vec<_> = BackendKind::ALL.iter().map(BackendKind::name).collect();
+                write!(
+                    f,
+                    "unknown backend `{}`, expected one of: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+            BackendError::Failed(errors) => {
+                write!(f, "no usable backend found")?;
+                for (kind, err) in errors {
+                    write!(f, "\n  {}: {}", kind, err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for BackendError {}
+
+/// Starts the `requested` backend, or the first usable one if none is given.
+///
+/// When no backend is given as argument, the `THING_BACKEND` environment variable is used if set.
+pub fn start(
+    event_loop: &mut EventLoop<CalloopData>,
+    data: &mut CalloopData,
+    requested: Option<&str>,
+    options: &BackendOptions,
+) -> Result<BackendKind, BackendError> {
+    let requested = requested
+        .map(str::to_owned)
+        .or_else(|| std::env::var(BACKEND_ENV).ok().filter(|v| !v.is_empty()));
+
+    let candidates = match requested {
+        Some(name) => vec![BackendKind::from_name(&name).ok_or(BackendError::Unknown(name))?],
+        None => BackendKind::detect(),
+    };
+
+    let mut errors = Vec::new();
+    for kind in candidates {
+        match kind.start(event_loop, data, options) {
+            Ok(()) => {
+                info!("Started {} backend", kind);
+                if options.dump_frames.is_some() && kind != BackendKind::Headless {
+                    warn!("Only the headless backend writes its frames to files");
+                }
+                return Ok(kind);
+            }
+            Err(err) => {
+                warn!(%err, "Unable to start {} backend", kind);
+                errors.push((kind, err));
+            }
+        }
+    }
+
+    Err(BackendError::Failed(errors))
+}
@@ -0,0 +1,235 @@
+use std::{fs, path::Path};
+
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            damage::OutputDamageTracker,
+            pixman::{PixmanError, PixmanRenderer},
+            Bind, ExportMem, Offscreen,
+        },
+    },
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{
+        calloop::{ping::make_ping, EventLoop},
+        pixman::Image,
+        wayland_server::DisplayHandle,
+    },
+    utils::{Buffer, Rectangle, Size, Transform},
+};
+use tracing::{error, info};
+
+use crate::{
+    backend::{output_scale, scheduler::submit_estimated_frame, BackendOptions, CalloopData},
+    render::output_elements,
+    state::ThingState,
+};
+
+/// Default size of the virtual outputs, in pixels
+pub const DEFAULT_OUTPUT_SIZE: (i32, i32) = (1920, 1080);
+
+/// A virtual output, rendered into an in-memory buffer
+struct HeadlessOutput {
+    output: Output,
+    damage_tracker: OutputDamageTracker,
+    buffer: Image<'static, 'static>,
+    buffer_size: Size<i32, Buffer>,
+    /// Age of `buffer`, 0 until it has been rendered once
+    age: usize,
+}
+
+/// Starts the headless backend, with `options.output_count` virtual outputs placed side by side.
+///
+/// Everything is rendered on the CPU with pixman, so this needs neither a display server nor a
+/// GPU. With `options.dump_frames`, every rendered frame is written to
+/// `<dump_frames>/<output name>.ppm`, replaced at once so that it is never read partly written.
+pub fn run(
+    event_loop: &mut EventLoop<CalloopData>,
+    data: &mut CalloopData,
+    options: &BackendOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let state = &mut data.state;
+
+    let mut renderer = PixmanRenderer::new()?;
+
+    let output_count = options.output_count.max(1);
+    let mut outputs = Vec::with_capacity(output_count);
+    for i in 0..output_count {
+        let output = Output::new(
+            format!("headless-{}", i + 1),
+            PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: "Thing".into(),
+                model: "Headless".into(),
+            },
+        );
+        let _global = output.create_global::<ThingState>(&data.dh);
+
+        let config = state.output_config(&output.name());
+        let mode = Mode {
+            size: config
+                .mode
+                .map(|mode| mode.size)
+                .unwrap_or_else(|| DEFAULT_OUTPUT_SIZE.into()),
+            refresh: config.mode.and_then(|mode| mode.refresh).unwrap_or(60_000),
+        };
+        output.change_current_state(
+            Some(mode),
+            Some(config.transform.unwrap_or(Transform::Normal)),
+            Some(output_scale(config.scale.unwrap_or(1.0))),
+            None,
+        );
+        output.set_preferred(mode);
+        // Placed side by side by `arrange_outputs` below
+        state.space.map_output(&output, (0, 0));
+
+        let buffer_size = mode.size.to_logical(1).to_buffer(1, Transform::Normal);
+        let buffer: Image<'static, 'static> =
+            renderer.create_buffer(Fourcc::Argb8888, buffer_size)?;
+        let damage_tracker = OutputDamageTracker::from_output(&output);
+
+        outputs.push(HeadlessOutput {
+            output,
+            damage_tracker,
+            buffer,
+            buffer_size,
+            age: 0,
+        });
+    }
+    state.arrange_outputs();
+    info!("Headless backend started with {} output(s)", outputs.len());
+
+    // Rendering, only done when the scheduler asks for it
+    let (ping, ping_source) = make_ping()?;
+    state.render_scheduler.set_waker(ping);
+    for headless in &outputs {
+        state.render_scheduler.queue_redraw(&headless.output);
+    }
+
+    let dump_frames = options.dump_frames.clone();
+    event_loop
+        .handle()
+        .insert_source(ping_source, move |_, _, data| {
+            render(
+                &mut data.dh,
+                &mut data.state,
+                &mut renderer,
+                &mut outputs,
+                dump_frames.as_deref(),
+            );
+        })?;
+
+    Ok(())
+}
+
+fn render(
+    dh: &mut DisplayHandle,
+    state: &mut ThingState,
+    renderer: &mut PixmanRenderer,
+    outputs: &mut [HeadlessOutput],
+    dump_frames: Option<&Path>,
+) {
+    for headless in outputs.iter_mut() {
+        if !state.render_scheduler.should_render(&headless.output) {
+            continue;
+        }
+        // There is no display, frames are "displayed" at the refresh rate of the output
+        submit_estimated_frame(state, &headless.output);
+
+        if let Err(err) = renderer.bind(headless.buffer.clone()) {
+            error!(?err, "Unable to bind output buffer");
+            continue;
+        }
+
+        let elements = output_elements(state, renderer, &headless.output);
+        let render_result = headless.damage_tracker.render_output(
+            renderer,
+            headless.age,
+            &elements,
+            state.config.background.0,
+        );
+        if let Err(render_err) = render_result {
+            error!(err = ?render_err, "Error when rendering output.");
+            continue;
+        }
+        // The same buffer is reused for every frame
+        headless.age = 1;
+        if let Some(dir) = dump_frames {
+            dump_frame(renderer, headless, dir);
+        }
+
+        state.send_frames(&headless.output);
+    }
+
+    state.space.refresh();
+    state.popup_manager.cleanup();
+    if let Err(err) = dh.flush_clients() {
+        error!(?err, "Error when flushing clients");
+    }
+}
+
+/// Writes the frame just rendered for `headless` to `dir`, as a PPM image named after the output
+fn dump_frame(renderer: &mut PixmanRenderer, headless: &HeadlessOutput, dir: &Path) {
+    let pixels = match read_pixels(renderer, headless.buffer_size) {
+        Ok(pixels) => pixels,
+        Err(err) => return error!(?err, "Unable to read the rendered frame"),
+    };
+    let path = dir.join(format!("{}.ppm", headless.output.name()));
+    let tmp_path = path.with_extension("tmp");
+    let res = fs::write(&tmp_path, encode_ppm(headless.buffer_size, &pixels))
+        .and_then(|_| fs::rename(&tmp_path, &path));
+    if let Err(err) = res {
+        error!(?err, "Unable to write the frame to {}", path.display());
+    }
+}
+
+/// Pixels of the bound buffer of `size`, as RGBA rows without padding
+fn read_pixels(
+    renderer: &mut PixmanRenderer,
+    size: Size<i32, Buffer>,
+) -> Result<Vec<u8>, PixmanError> {
+    let mapping =
+        renderer.copy_framebuffer(Rectangle::from_loc_and_size((0, 0), size), Fourcc::Abgr8888)?;
+    Ok(renderer.map_texture(&mapping)?.to_vec())
+}
+
+/// Binary PPM image of `size` made of RGBA `pixels`, without their alpha
+fn encode_ppm(size: Size<i32, Buffer>, pixels: &[u8]) -> Vec<u8> {
+    let mut ppm = format!("P6\n{} {}\n255\n", size.w, size.h).into_bytes();
+    ppm.extend(pixels.chunks_exact(4).flat_map(|pixel| &pixel[..3]));
+    ppm
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::backend::renderer::{Frame, Renderer};
+
+    use super::*;
+
+    #[test]
+    fn rendered_pixels_are_read_back() {
+        let mut renderer = PixmanRenderer::new().unwrap();
+        let size: Size<i32, Buffer> = (4, 2).into();
+        let buffer: Image<'static, 'static> =
+            renderer.create_buffer(Fourcc::Argb8888, size).unwrap();
+        renderer.bind(buffer).unwrap();
+
+        let mut frame = renderer.render((4, 2).into(), Transform::Normal).unwrap();
+        let damage = Rectangle::from_loc_and_size((0, 0), (4, 2));
+        frame.clear([1.0, 0.0, 0.0, 1.0], &[damage]).unwrap();
+        frame.finish().unwrap();
+
+        let pixels = read_pixels(&mut renderer, size).unwrap();
+        assert_eq!(pixels.len(), 4 * 2 * 4);
+        assert!(pixels
+            .chunks_exact(4)
+            .all(|pixel| pixel == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn ppm_drops_alpha() {
+        let ppm = encode_ppm((2, 1).into(), &[1, 2, 3, 255, 4, 5, 6, 0]);
+        assert_eq!(ppm, b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06");
+    }
+}
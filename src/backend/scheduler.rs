@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use smithay::{
+    output::Output,
+    reexports::calloop::{
+        ping::Ping,
+        timer::{TimeoutAction, Timer},
+    },
+};
+use tracing::error;
+
+use crate::state::ThingState;
+
+/// Redraw state of an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RedrawState {
+    /// Nothing changed since the last frame
+    Idle,
+    /// The output has to be redrawn as soon as possible
+    Queued,
+    /// A frame was submitted, but is not displayed yet
+    WaitingForFrame {
+        /// Whether a redraw got queued in the meantime
+        queued: bool,
+    },
+}
+
+/// Decides when outputs have to be redrawn.
+///
+/// Anything changing what is displayed queues a redraw of the affected outputs, which wakes the
+/// backend up through its waker. The backend then only renders the queued outputs, at most once
+/// per displayed frame, and sleeps the rest of the time.
+#[derive(Default)]
+pub struct RenderScheduler {
+    waker: Option<Ping>,
+    outputs: Vec<(Output, RedrawState)>,
+}
+
+impl RenderScheduler {
+    /// Sets the ping used to wake the backend up when a redraw gets queued
+    pub fn set_waker(&mut self, waker: Ping) {
+        self.waker = Some(waker);
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = &self.waker {
+            waker.ping();
+        }
+    }
+
+    fn state_mut(&mut self, output: &Output) -> &mut RedrawState {
+        let index = match self.outputs.iter().position(|(o, _)| o == output) {
+            Some(index) => index,
+            None => {
+                self.outputs.push((output.clone(), RedrawState::Idle));
+                self.outputs.len() - 1
+            }
+        };
+        &mut self.outputs[index].1
+    }
+
+    /// Queues a redraw of `output`
+    pub fn queue_redraw(&mut self, output: &Output) {
+        let state = self.state_mut(output);
+        let wake = match state {
+            RedrawState::Idle => {
+                *state = RedrawState::Queued;
+                true
+            }
+            RedrawState::WaitingForFrame { queued } => {
+                *queued = true;
+                false
+            }
+            RedrawState::Queued => false,
+        };
+        if wake {
+            self.wake();
+        }
+    }
+
+    /// Whether `output` has to be rendered now
+    pub fn should_render(&self, output: &Output) -> bool {
+        self.outputs
+            .iter()
+            .any(|(o, state)| o == output && *state == RedrawState::Queued)
+    }
+
+    /// Should be called once a frame has been submitted for `output`
+    pub fn frame_submitted(&mut self, output: &Output) {
+        *self.state_mut(output) = RedrawState::WaitingForFrame { queued: false };
+    }
+
+    /// Should be called once the last frame submitted for `output` is displayed.
+    ///
+    /// Wakes the backend up again if a redraw got queued in the meantime.
+    pub fn frame_presented(&mut self, output: &Output) {
+        let state = self.state_mut(output);
+        let wake = match *state {
+            RedrawState::WaitingForFrame { queued: true } => {
+                *state = RedrawState::Queued;
+                true
+            }
+            RedrawState::WaitingForFrame { queued: false } => {
+                *state = RedrawState::Idle;
+                false
+            }
+            RedrawState::Idle | RedrawState::Queued => false,
+        };
+        if wake {
+            self.wake();
+        }
+    }
+
+    /// Forgets about an output that is not displayed anymore
+    pub fn remove_output(&mut self, output: &Output) {
+        self.outputs.retain(|(o, _)| o != output);
+    }
+}
+
+/// Duration of a frame on `output`, based on its refresh rate
+pub fn frame_duration(output: &Output) -> Duration {
+    let refresh = output
+        .current_mode()
+        .map(|mode| mode.refresh)
+        .filter(|refresh| *refresh > 0)
+        .unwrap_or(60_000);
+    Duration::from_secs_f64(1_000.0 / refresh as f64)
+}
+
+/// Marks a frame as submitted for `output`, and as presented one refresh cycle later.
+///
+/// This is for backends which are not told when their frames are actually displayed.
+pub fn submit_estimated_frame(state: &mut ThingState, output: &Output) {
+    state.render_scheduler.frame_submitted(output);
+
+    let output_clone = output.clone();
+    let res = state.loop_handle.insert_source(
+        Timer::from_duration(frame_duration(output)),
+        move |_, _, data| {
+            data.state.render_scheduler.frame_presented(&output_clone);
+            TimeoutAction::Drop
+        },
+    );
+    if let Err(err) = res {
+        error!(?err, "Unable to schedule the next frame");
+        state.render_scheduler.frame_presented(output);
+    }
+}
@@ -1,159 +1,326 @@
-use std::time::Duration;
-
 use smithay::{
     backend::{
+        allocator::Fourcc,
         renderer::{
-            damage::OutputDamageTracker, element::surface::WaylandSurfaceRenderElement,
-            glow::GlowRenderer, ImportEgl,
+            damage::OutputDamageTracker,
+            element::{
+                texture::{TextureBuffer, TextureRenderElement},
+                Kind,
+            },
+            gles::GlesTexture,
+            glow::GlowRenderer,
+            Bind, ImportEgl, Offscreen,
         },
-        winit::{self, WinitEvent, WinitEventLoop, WinitGraphicsBackend},
+        winit::{self, WinitEvent, WinitGraphicsBackend},
     },
-    desktop::space::render_output,
     output::{Mode, Output, PhysicalProperties, Subpixel},
     reexports::{
-        calloop::{
-            timer::{TimeoutAction, Timer},
-            EventLoop, LoopSignal,
-        },
+        calloop::{ping::make_ping, EventLoop},
         wayland_server::DisplayHandle,
+        winit::{
+            dpi::{LogicalSize, PhysicalSize},
+            window::WindowAttributes,
+        },
     },
-    utils::{Rectangle, Transform},
+    utils::{Buffer, Physical, Rectangle, Size, Transform},
 };
 use tracing::{error, info};
 
-use crate::{state::ThingState, backend::CalloopData};
+use crate::{
+    backend::{output_scale, scheduler::submit_estimated_frame, BackendOptions, CalloopData},
+    render::output_elements,
+    state::ThingState,
+};
 
+/// Starts the winit backend, nested in a window of the host compositor.
+///
+/// Winit can only create one event loop per process, so when several outputs are requested the
+/// window is split in as many columns, each backing its own output with its own scale and
+/// transform.
 pub fn run(
     event_loop: &mut EventLoop<CalloopData>,
     data: &mut CalloopData,
+    options: &BackendOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let state = &mut data.state;
 
-    let (mut backend, mut winit) = winit::init::<GlowRenderer>()?;
+    let output_count = options.output_count.max(1);
+    let output_names: Vec<String> = match output_count {
+        1 => vec!["winit".to_string()],
+        _ => (1..=output_count).map(|i| format!("winit-{}", i)).collect(),
+    };
 
-    let mode = Mode {
-        size: backend.window_size(),
-        refresh: 60_000,
+    // The window is sized after the first output
+    let config = state.output_config(&output_names[0]);
+    let attributes = WindowAttributes::default()
+        .with_title("Thing")
+        .with_visible(true);
+    let attributes = match config.mode {
+        Some(mode) => attributes.with_inner_size(PhysicalSize::new(
+            mode.size.w * output_count as i32,
+            mode.size.h,
+        )),
+        None => attributes.with_inner_size(LogicalSize::new(1280.0 * output_count as f64, 800.0)),
     };
+    let (mut backend, winit) = winit::init_from_attributes::<GlowRenderer>(attributes)?;
+    let refresh = config.mode.and_then(|mode| mode.refresh).unwrap_or(60_000);
 
-    let output = Output::new(
-        "winit".to_string(),
-        PhysicalProperties {
-            size: (0, 0).into(),
-            subpixel: Subpixel::Unknown,
-            make: "Thing".into(),
-            model: "Winit".into(),
-        },
-    );
-    let _global = output.create_global::<ThingState>(&data.dh);
-    output.change_current_state(
-        Some(mode),
-        Some(Transform::Flipped180),
-        None,
-        Some((0, 0).into()),
-    );
-    output.set_preferred(mode);
+    let outputs: Vec<Output> = output_names
+        .into_iter()
+        .map(|name| {
+            let output = Output::new(
+                name,
+                PhysicalProperties {
+                    size: (0, 0).into(),
+                    subpixel: Subpixel::Unknown,
+                    make: "Thing".into(),
+                    model: "Winit".into(),
+                },
+            );
+            let _global = output.create_global::<ThingState>(&data.dh);
+            output
+        })
+        .collect();
+
+    for output in &outputs {
+        state.space.map_output(output, (0, 0));
+    }
+    let host_scale = backend.window().scale_factor();
+    let window_size = backend.window_size();
+    layout_outputs(state, &outputs, window_size, host_scale, refresh);
 
     // Enables hardware acceleration for client surfaces
     if backend.renderer().bind_wl_display(&data.dh).is_ok() {
         info!("EGL Enabled");
     }
 
-    state.space.map_output(&output, (0, 0));
-
-    let mut damage_tracker = OutputDamageTracker::from_output(&output);
+    let mut columns: Vec<Column> = outputs.iter().cloned().map(Column::new).collect();
+    let mut damage_tracker = WindowDamageTracker::default();
 
-    std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
+    // Rendering, only done when the scheduler asks for it
+    let (ping, ping_source) = make_ping()?;
+    state.render_scheduler.set_waker(ping);
+    for output in &outputs {
+        state.render_scheduler.queue_redraw(output);
+    }
 
-    let loop_signal = event_loop.get_signal();
     event_loop
         .handle()
-        .insert_source(Timer::immediate(), move |_instant, _, data| {
-            dispatch(
+        .insert_source(ping_source, move |_, _, data| {
+            let should_render = columns
+                .iter()
+                .any(|column| data.state.render_scheduler.should_render(&column.output));
+            if !should_render {
+                return;
+            }
+            // Every output is drawn in the same window, so they are all rendered together
+            render(
                 &mut data.dh,
                 &mut data.state,
                 &mut backend,
-                &mut winit,
-                &output,
+                &mut columns,
                 &mut damage_tracker,
-                &loop_signal,
             );
-            // Draw 60 time a second
-            TimeoutAction::ToDuration(Duration::from_millis(16))
+            // The host compositor does not tell us when the frame is displayed
+            for column in &columns {
+                submit_estimated_frame(&mut data.state, &column.output);
+            }
+        })?;
+
+    // Winit events
+    let loop_signal = event_loop.get_signal();
+    event_loop
+        .handle()
+        .insert_source(winit, move |event, _, data| match event {
+            WinitEvent::Resized { size, scale_factor } => {
+                layout_outputs(&mut data.state, &outputs, size, scale_factor, refresh);
+            }
+            WinitEvent::Input(input) => data.state.process_input_event(input),
+            WinitEvent::Redraw => {
+                for output in &outputs {
+                    data.state.render_scheduler.queue_redraw(output);
+                }
+            }
+            WinitEvent::CloseRequested => loop_signal.stop(),
+            _ => (),
         })?;
 
     Ok(())
 }
 
-fn dispatch(
-    // display: &mut Display<ThingState>,
+/// Splits a window of `window_size` in one column per output, from left to right, and places
+/// the outputs side by side accordingly.
+///
+/// The mode of each output is the size of its column, before its transform. The outputs follow
+/// the scale factor of the host, unless one is configured.
+fn layout_outputs(
+    state: &mut ThingState,
+    outputs: &[Output],
+    window_size: Size<i32, Physical>,
+    host_scale: f64,
+    refresh: i32,
+) {
+    let column_width = window_size.w / outputs.len() as i32;
+    for output in outputs {
+        let config = state.output_config(&output.name());
+        let mode = Mode {
+            size: (column_width, window_size.h).into(),
+            refresh,
+        };
+        output.change_current_state(
+            Some(mode),
+            Some(config.transform.unwrap_or(Transform::Normal)),
+            Some(output_scale(config.scale.unwrap_or(host_scale))),
+            None,
+        );
+        output.set_preferred(mode);
+    }
+    state.arrange_outputs();
+}
+
+/// An output, rendered to a texture with its own scale and transform before being drawn in its
+/// column of the window
+struct Column {
+    output: Output,
+    damage_tracker: OutputDamageTracker,
+    /// Texture the output is rendered to, recreated when the mode of the output changes
+    target: Option<ColumnTarget>,
+    /// Age of the texture, 0 until it has been rendered once
+    age: usize,
+}
+
+struct ColumnTarget {
+    texture: GlesTexture,
+    buffer: TextureBuffer<GlesTexture>,
+    size: Size<i32, Buffer>,
+}
+
+impl Column {
+    fn new(output: Output) -> Self {
+        Self {
+            damage_tracker: OutputDamageTracker::from_output(&output),
+            output,
+            target: None,
+            age: 0,
+        }
+    }
+
+    /// Renders the output to its texture
+    fn render(&mut self, state: &mut ThingState, renderer: &mut GlowRenderer) {
+        let Some(mode) = self.output.current_mode() else {
+            return;
+        };
+        let size = mode.size.to_logical(1).to_buffer(1, Transform::Normal);
+        if self.target.as_ref().map(|target| target.size) != Some(size) {
+            let texture: GlesTexture = match renderer.create_buffer(Fourcc::Abgr8888, size) {
+                Ok(texture) => texture,
+                Err(err) => return error!(?err, "Unable to create output texture"),
+            };
+            let buffer =
+                TextureBuffer::from_texture(renderer, texture.clone(), 1, Transform::Normal, None);
+            self.target = Some(ColumnTarget {
+                texture,
+                buffer,
+                size,
+            });
+            self.age = 0;
+        }
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        if let Err(err) = renderer.bind(target.texture.clone()) {
+            return error!(?err, "Unable to bind output texture");
+        }
+        let elements = output_elements(state, renderer, &self.output);
+        let render_result = self.damage_tracker.render_output(
+            renderer,
+            self.age,
+            &elements,
+            state.config.background.0,
+        );
+        match render_result {
+            // The same texture is reused for every frame
+            Ok(_) => self.age = 1,
+            Err(err) => error!(?err, "Error when rendering output"),
+        }
+    }
+}
+
+/// Damage tracker of the whole window, compensating for the y-flip of its framebuffer.
+///
+/// The tracker is rebuilt whenever the size of the window changes.
+#[derive(Default)]
+struct WindowDamageTracker {
+    tracker: Option<(OutputDamageTracker, Size<i32, Physical>)>,
+}
+
+impl WindowDamageTracker {
+    fn get(&mut self, size: Size<i32, Physical>) -> &mut OutputDamageTracker {
+        if !matches!(&self.tracker, Some((_, tracked)) if *tracked == size) {
+            let tracker = OutputDamageTracker::new(size, 1.0, Transform::Flipped180);
+            self.tracker = Some((tracker, size));
+        }
+        &mut self.tracker.as_mut().unwrap().0
+    }
+}
+
+fn render(
     dh: &mut DisplayHandle,
     state: &mut ThingState,
     backend: &mut WinitGraphicsBackend<GlowRenderer>,
-    winit: &mut WinitEventLoop,
-    output: &Output,
-    damage_tracker: &mut OutputDamageTracker,
-    loop_signal: &LoopSignal,
+    columns: &mut [Column],
+    damage_tracker: &mut WindowDamageTracker,
 ) {
-    // Dispatch winit events
-    let _dispatch_status = winit.dispatch_new_events(|event| match event {
-        WinitEvent::Resized {
-            size,
-            scale_factor: _,
-        } => output.change_current_state(
-            Some(Mode {
-                size,
-                refresh: 60_000,
-            }),
+    for column in columns.iter_mut() {
+        column.render(state, backend.renderer());
+    }
+
+    // The textures of the outputs, side by side
+    let mut elements: Vec<TextureRenderElement<GlesTexture>> = Vec::new();
+    let mut column_x = 0;
+    for column in columns.iter() {
+        let Some(target) = &column.target else {
+            continue;
+        };
+        elements.push(TextureRenderElement::from_texture_buffer(
+            (column_x as f64, 0.0),
+            &target.buffer,
             None,
             None,
             None,
-        ),
-        WinitEvent::Input(input) => state.process_input_event(input),
-        WinitEvent::CloseRequested => loop_signal.stop(),
-        _ => (),
-    });
-
-    // TODO: handle `PumpStatus::Exit` here
+            Kind::Unspecified,
+        ));
+        column_x += target.size.w;
+    }
 
-    backend.bind().unwrap();
+    if let Err(err) = backend.bind() {
+        return error!(?err, "Unable to bind the window");
+    }
 
-    let render_result = render_output::<_, WaylandSurfaceRenderElement<GlowRenderer>, _, _>(
-        &output,
+    let window_size = backend.window_size();
+    let render_result = damage_tracker.get(window_size).render_output(
         backend.renderer(),
-        1.0,
         0,
-        [&state.space],
-        &[],
-        damage_tracker,
-        [0.0, 0.0, 0.0, 1.0],
+        &elements,
+        state.config.background.0,
     );
     if let Err(render_err) = render_result {
         return tracing::error!(err = ?render_err, "Error when rendering output.");
     }
 
-    let swap_result = backend.submit(Some(&[Rectangle::from_loc_and_size(
-        (0, 0),
-        backend.window_size(),
-    )]));
+    let swap_result = backend.submit(Some(&[Rectangle::from_loc_and_size((0, 0), window_size)]));
     if let Err(swap_err) = swap_result {
         return tracing::error!(err = ?swap_err, "Error when swapping backbuffer to window.");
     }
 
-    state.space.elements().for_each(|window| {
-        window.send_frame(
-            &output,
-            state.start_time.elapsed(),
-            Some(Duration::ZERO),
-            |_, _| Some(output.clone()),
-        )
-    });
+    for column in columns.iter() {
+        state.send_frames(&column.output);
+    }
 
     state.space.refresh();
     state.popup_manager.cleanup();
     if let Err(err) = dh.flush_clients() {
         error!(?err, "Error when flushing clients");
     }
-
-    backend.window().request_redraw();
 }
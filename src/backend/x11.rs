@@ -1,52 +1,63 @@
-use std::{cell::Cell, collections::HashSet, rc::Rc, time::Duration};
+use std::collections::HashSet;
 
 use smithay::{
     backend::{
         allocator::{
             dmabuf::DmabufAllocator,
-            gbm::{GbmAllocator, GbmDevice},
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
         },
         egl::{EGLContext, EGLDisplay},
-        renderer::{
-            damage::OutputDamageTracker, element::surface::WaylandSurfaceRenderElement,
-            glow::GlowRenderer, Bind,
-        },
-        x11::{WindowBuilder, X11Backend, X11Event},
+        renderer::{damage::OutputDamageTracker, glow::GlowRenderer, Bind, ImportEgl},
+        x11::{WindowBuilder, X11Backend, X11Event, X11Surface},
     },
-    desktop::space::render_output,
     output::{Mode, Output, PhysicalProperties, Subpixel},
-    reexports::calloop::{
-        timer::{TimeoutAction, Timer},
-        EventLoop,
+    reexports::{
+        calloop::{ping::make_ping, EventLoop},
+        wayland_server::DisplayHandle,
     },
-    utils::{DeviceFd, Rectangle, Size, Transform},
+    utils::{DeviceFd, Transform},
 };
+use tracing::{error, info, warn};
 
-use crate::{state::ThingState, CalloopData};
+use crate::{
+    backend::{output_scale, BackendOptions, CalloopData},
+    render::output_elements,
+    state::ThingState,
+};
 
 pub fn run(
     event_loop: &mut EventLoop<CalloopData>,
     data: &mut CalloopData,
+    options: &BackendOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let display = &mut data.display;
     let state = &mut data.state;
 
-    // Creating the x11 backend
+    if options.output_count > 1 {
+        warn!("The x11 backend only supports one output, ignoring --outputs");
+    }
 
+    // Creating the x11 backend
+    //
+    // This connects to the host X server right away, before XWayland gets a chance to replace
+    // `DISPLAY` with its own display.
     let backend = X11Backend::new()?;
     let x_handle = backend.handle();
 
-    let window = WindowBuilder::new()
-        .title("X11")
-        .build(&x_handle)
-        .expect("Error building the x11 window");
+    let config = state.output_config("x11");
+    let refresh = config.mode.and_then(|mode| mode.refresh).unwrap_or(60_000);
+
+    let mut window_builder = WindowBuilder::new().title("Thing");
+    if let Some(mode) = config.mode {
+        window_builder = window_builder.size((mode.size.w as u16, mode.size.h as u16).into());
+    }
+    let window = window_builder.build(&x_handle)?;
 
-    let (drm_node, fd) = x_handle.drm_node()?;
+    let (_drm_node, fd) = x_handle.drm_node()?;
 
     let gbm = GbmDevice::new(DeviceFd::from(fd))?;
 
-    let egl = EGLDisplay::new(gbm.clone()).expect("Failed to create EGLDisplay");
-    let context = EGLContext::new(&egl).expect("Failed to create EGL Context");
+    let egl = unsafe { EGLDisplay::new(gbm.clone())? };
+    let context = EGLContext::new(&egl)?;
     let modifiers: HashSet<_> = context
         .dmabuf_render_formats()
         .iter()
@@ -55,17 +66,18 @@ pub fn run(
 
     let mut surface = x_handle.create_surface(
         &window,
-        DmabufAllocator(GbmAllocator::new(
-            gbm,
-            smithay::backend::allocator::gbm::GbmBufferFlags::RENDERING,
-        )),
+        DmabufAllocator(GbmAllocator::new(gbm, GbmBufferFlags::RENDERING)),
         modifiers.into_iter(),
     )?;
 
     // Renderer
 
-    let mut renderer =
-        unsafe { GlowRenderer::new(context).expect("Failed to create glow renderer") };
+    let mut renderer = unsafe { GlowRenderer::new(context)? };
+
+    // Enables hardware acceleration for client surfaces
+    if renderer.bind_wl_display(&data.dh).is_ok() {
+        info!("EGL Enabled");
+    }
 
     // Output creation
 
@@ -74,112 +86,138 @@ pub fn run(
             let size = window.size();
             (size.w as i32, size.h as i32).into()
         },
-        refresh: 60_000,
+        refresh,
     };
 
     let output = Output::new(
-        "winit".to_string(),
+        "x11".to_string(),
         PhysicalProperties {
             size: (0, 0).into(),
             subpixel: Subpixel::Unknown,
             make: "Thing".into(),
-            model: "Winit".into(),
+            model: "X11".into(),
         },
     );
-    let _global = output.create_global::<ThingState>(&display.handle());
+    let _global = output.create_global::<ThingState>(&data.dh);
+    // We render into buffers we allocated ourselves, so unlike winit there is no y-flip to
+    // compensate for.
     output.change_current_state(
         Some(mode),
-        Some(Transform::Flipped180),
-        None,
+        Some(config.transform.unwrap_or(Transform::Normal)),
+        Some(output_scale(config.scale.unwrap_or(1.0))),
         Some((0, 0).into()),
     );
     output.set_preferred(mode);
 
     state.space.map_output(&output, (0, 0));
+    state.workspaces_changed();
 
     let mut damage_tracker = OutputDamageTracker::from_output(&output);
 
-    std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
+    // Rendering, only done when the scheduler asks for it
+    let (ping, ping_source) = make_ping()?;
+    state.render_scheduler.set_waker(ping);
+    state.render_scheduler.queue_redraw(&output);
 
-    // Event Loops
+    let redraw_output = output.clone();
+    event_loop
+        .handle()
+        .insert_source(ping_source, move |_, _, data| {
+            if !data.state.render_scheduler.should_render(&redraw_output) {
+                return;
+            }
+            data.state.render_scheduler.frame_submitted(&redraw_output);
+            let submitted = render(
+                &mut data.dh,
+                &mut data.state,
+                &mut renderer,
+                &mut surface,
+                &redraw_output,
+                &mut damage_tracker,
+            );
+            if !submitted {
+                // No `PresentCompleted` event will come for this frame
+                data.state.render_scheduler.frame_presented(&redraw_output);
+            }
+        })?;
 
-    let render = Rc::new(Cell::new(false));
-    let render_clone = render.clone();
+    // X11 events
 
-    let output_clone = output.clone();
+    let loop_signal = event_loop.get_signal();
     event_loop
         .handle()
         .insert_source(backend, move |event, _, data| match event {
-            X11Event::Input(event) => data.state.process_input_event(event),
-            X11Event::Resized {
-                new_size,
-                window_id: _,
-            } => {
+            X11Event::Input { event, .. } => data.state.process_input_event(event),
+            X11Event::Resized { new_size, .. } => {
+                let size = new_size.to_physical(1);
                 let mode = Mode {
-                    size: {
-                        let size = new_size.to_physical(1);
-                        (size.w as i32, size.h as i32).into()
-                    },
-                    refresh: 60_000,
+                    size: (size.w as i32, size.h as i32).into(),
+                    refresh,
                 };
 
-                output_clone.delete_mode(output_clone.current_mode().unwrap());
-                output_clone.change_current_state(
-                    Some(mode),
-                    Some(Transform::Flipped180),
-                    None,
-                    Some((0, 0).into()),
-                );
-                output_clone.set_preferred(mode);
+                if let Some(current_mode) = output.current_mode() {
+                    output.delete_mode(current_mode);
+                }
+                output.change_current_state(Some(mode), None, None, None);
+                output.set_preferred(mode);
 
-                state.space.map_output(&output_clone, (0, 0));
+                data.state.space.map_output(&output, (0, 0));
+                data.state.render_scheduler.queue_redraw(&output);
             }
-            X11Event::PresentCompleted { .. } | X11Event::Refresh { .. } => render_clone.set(true),
-            _ => (),
-        })
-        .unwrap();
-
-    event_loop
-        .handle()
-        .insert_source(Timer::immediate(), move |event, _, data| {
-            // TODO: Maybe move this to an external function
-
-            let display = &mut data.display;
-            let state = &mut data.state;
-
-            if render.get() {
-                let (buffer, age) = surface.buffer().unwrap();
-                renderer.bind(buffer).unwrap();
-
-                render_output::<_, WaylandSurfaceRenderElement<GlowRenderer>, _, _>(
-                    &output,
-                    &mut renderer,
-                    1.0,
-                    0,
-                    [&state.space],
-                    &[],
-                    &mut damage_tracker,
-                    [0.1, 0.1, 0.1, 1.0],
-                )
-                .unwrap();
-
-                surface.submit().unwrap();
-
-                state.space.elements().for_each(|window| {
-                    window.send_frame(
-                        &output,
-                        state.start_time.elapsed(),
-                        Some(Duration::ZERO),
-                        |_, _| Some(output.clone()),
-                    )
-                });
+            X11Event::PresentCompleted { .. } => {
+                data.state.render_scheduler.frame_presented(&output)
             }
-
-            state.space.refresh();
-            display.flush_clients().unwrap();
-            TimeoutAction::ToDuration(Duration::ZERO)
-        })
-        .unwrap();
+            X11Event::Refresh { .. } => data.state.render_scheduler.queue_redraw(&output),
+            X11Event::CloseRequested { window_id } if window_id == window.id() => {
+                loop_signal.stop()
+            }
+            _ => (),
+        })?;
 
     Ok(())
 }
+
+/// Renders `output` and submits it, returns whether a frame got submitted
+fn render(
+    dh: &mut DisplayHandle,
+    state: &mut ThingState,
+    renderer: &mut GlowRenderer,
+    surface: &mut X11Surface,
+    output: &Output,
+    damage_tracker: &mut OutputDamageTracker,
+) -> bool {
+    let (buffer, age) = match surface.buffer() {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            error!(?err, "Unable to get a buffer from the X11 surface");
+            return false;
+        }
+    };
+    if let Err(err) = renderer.bind(buffer) {
+        error!(?err, "Unable to bind X11 surface buffer");
+        return false;
+    }
+
+    let elements = output_elements(state, renderer, output);
+    let render_result =
+        damage_tracker.render_output(renderer, age as usize, &elements, state.config.background.0);
+    if let Err(render_err) = render_result {
+        error!(err = ?render_err, "Error when rendering output.");
+        return false;
+    }
+
+    if let Err(err) = surface.submit() {
+        error!(?err, "Error when presenting X11 surface");
+        return false;
+    }
+
+    state.send_frames(output);
+
+    state.space.refresh();
+    state.popup_manager.cleanup();
+    if let Err(err) = dh.flush_clients() {
+        error!(?err, "Error when flushing clients");
+    }
+
+    true
+}
@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use smithay::utils::{Physical, Size, Transform};
+use wayland_compositor_thing::config::{parse_size, parse_transform};
+
+/// A small wayland compositor
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Backend to run on (winit, x11 or headless).
+    ///
+    /// Detected from the environment when not given, unless `THING_BACKEND` is set.
+    #[arg(long, short)]
+    pub backend: Option<String>,
+
+    /// Name of the wayland socket, picked automatically when not given
+    #[arg(long, short)]
+    pub socket: Option<String>,
+
+    /// Path of the configuration file, `$XDG_CONFIG_HOME/thing/config.toml` by default.
+    ///
+    /// The file is reloaded whenever it changes.
+    #[arg(long, short)]
+    pub config: Option<PathBuf>,
+
+    /// Do not start XWayland
+    #[arg(long)]
+    pub no_xwayland: bool,
+
+    /// Command to run once the compositor is ready.
+    ///
+    /// It is run with `sh -c`, with `WAYLAND_DISPLAY` and `DISPLAY` pointing to this compositor.
+    #[arg(long)]
+    pub spawn: Option<String>,
+
+    /// Wait for XWayland to be ready before running the `--spawn` command
+    #[arg(long, requires = "spawn")]
+    pub wait_for_xwayland: bool,
+
+    /// Stop the compositor when the `--spawn` command exits, and exit with its exit code
+    #[arg(long, requires = "spawn")]
+    pub exit_with_spawn: bool,
+
+    /// Size of the outputs of nested and headless backends, as `WIDTHxHEIGHT`.
+    ///
+    /// This, `--scale` and `--transform` take precedence over the configuration file.
+    #[arg(long, value_parser = parse_size)]
+    pub output_size: Option<Size<i32, Physical>>,
+
+    /// Number of outputs to create, on backends supporting more than one
+    #[arg(long, default_value_t = 1)]
+    pub outputs: usize,
+
+    /// Directory where the headless backend writes the last frame of each output, as
+    /// `<output name>.ppm`, for tests to check what is displayed
+    #[arg(long)]
+    pub dump_frames: Option<PathBuf>,
+
+    /// Scale factor of the outputs, integer or fractional (e.g. `2` or `1.5`).
+    ///
+    /// Nested backends follow the scale factor of the host when not given.
+    #[arg(long, value_parser = parse_scale)]
+    pub scale: Option<f64>,
+
+    /// Transform of the outputs: normal, 90, 180, 270, flipped, flipped-90, flipped-180 or
+    /// flipped-270.
+    ///
+    /// Rotations are counter-clockwise.
+    #[arg(long, value_parser = parse_transform)]
+    pub transform: Option<Transform>,
+
+    /// Log filter, in the same format as `RUST_LOG` (e.g. `info` or `wayland_compositor_thing=debug`)
+    #[arg(long)]
+    pub log_level: Option<String>,
+
+    /// Format of the logs
+    #[arg(long, value_enum, default_value_t = LogFormat::Compact)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Compact,
+    Full,
+    Pretty,
+}
+
+fn parse_scale(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        _ => Err(format!("`{}` is not a valid scale factor", s)),
+    }
+}
@@ -0,0 +1,629 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{de, Deserialize, Deserializer};
+use smithay::{
+    input::keyboard::XkbConfig,
+    utils::{Physical, Size, Transform},
+};
+
+use self::bindings::{Keybindings, Mousebindings};
+
+pub mod bindings;
+
+/// Configuration of the compositor, loaded from a TOML file.
+///
+/// Every field has a default value, so an empty file is a valid configuration.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Colour drawn behind the windows
+    pub background: Color,
+    pub focus: FocusConfig,
+    pub keyboard: KeyboardConfig,
+    /// Key combinations triggering compositor actions, replacing the default ones.
+    ///
+    /// `Ctrl+Alt+BackSpace` always stops the compositor, and cannot be bound to anything else.
+    pub keybindings: Keybindings,
+    /// Modifiers and pointer buttons triggering compositor actions, replacing the default ones.
+    ///
+    /// Actions on a window apply to the window under the pointer.
+    pub mousebindings: Mousebindings,
+    /// Settings applied to every output
+    pub output: OutputConfig,
+    /// Settings of specific outputs, by name, taking precedence over `output`
+    pub outputs: HashMap<String, OutputConfig>,
+    pub placement: Placement,
+    /// Settings of the windows matching patterns, written as `[[rules]]` tables.
+    ///
+    /// When several rules match a window, the settings of the last ones take precedence.
+    pub rules: Vec<WindowRule>,
+    pub snapping: SnappingConfig,
+    pub switcher: SwitcherConfig,
+    pub tiling: TilingConfig,
+    /// Number of workspaces of each output
+    pub workspaces: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            background: Color([0.0, 0.0, 0.0, 1.0]),
+            focus: FocusConfig::default(),
+            keyboard: KeyboardConfig::default(),
+            keybindings: Keybindings::default(),
+            mousebindings: Mousebindings::default(),
+            output: OutputConfig::default(),
+            outputs: HashMap::new(),
+            placement: Placement::default(),
+            rules: Vec::new(),
+            snapping: SnappingConfig::default(),
+            switcher: SwitcherConfig::default(),
+            tiling: TilingConfig::default(),
+            workspaces: 9,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct FocusConfig {
+    pub policy: FocusPolicy,
+    /// Whether windows are raised when they get focused with the pointer
+    pub raise_on_focus: bool,
+    /// Whether windows are raised when clicked, even if they are already focused
+    pub raise_on_click: bool,
+}
+
+impl Default for FocusConfig {
+    fn default() -> Self {
+        Self {
+            policy: FocusPolicy::default(),
+            raise_on_focus: false,
+            raise_on_click: true,
+        }
+    }
+}
+
+/// How the pointer moves the keyboard focus
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FocusPolicy {
+    /// Windows are focused when clicked
+    #[default]
+    Click,
+    /// Windows are focused when the pointer enters them, and nothing is focused when it is over
+    /// the background
+    FollowsMouse,
+    /// Windows are focused when the pointer enters them, and keep the focus when it leaves them
+    /// for the background
+    Sloppy,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct KeyboardConfig {
+    /// Delay before a held key starts repeating, in milliseconds
+    pub repeat_delay: i32,
+    /// Number of repetitions per second
+    pub repeat_rate: i32,
+    /// XKB rules, the system default is used if empty
+    pub rules: String,
+    /// XKB model, the system default is used if empty
+    pub model: String,
+    /// XKB layouts, separated by commas (e.g. `us,fr`)
+    pub layout: String,
+    /// XKB variants, one for each layout, separated by commas
+    pub variant: String,
+    /// XKB options, separated by commas (e.g. `caps:escape,compose:ralt`)
+    pub options: Option<String>,
+}
+
+impl KeyboardConfig {
+    pub fn xkb_config(&self) -> XkbConfig<'_> {
+        XkbConfig {
+            rules: &self.rules,
+            model: &self.model,
+            layout: &self.layout,
+            variant: &self.variant,
+            options: self.options.clone(),
+        }
+    }
+
+    /// Whether the XKB settings of `self` and `other` are the same
+    pub fn same_keymap(&self, other: &KeyboardConfig) -> bool {
+        self.rules == other.rules
+            && self.model == other.model
+            && self.layout == other.layout
+            && self.variant == other.variant
+            && self.options == other.options
+    }
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        Self {
+            repeat_delay: 200,
+            repeat_rate: 200,
+            rules: String::new(),
+            model: String::new(),
+            layout: String::new(),
+            variant: String::new(),
+            options: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct OutputConfig {
+    /// Mode of the output, as `WIDTHxHEIGHT` or `WIDTHxHEIGHT@REFRESH`, only applied at startup
+    #[serde(deserialize_with = "deserialize_mode")]
+    pub mode: Option<OutputMode>,
+    /// Scale factor, integer or fractional
+    pub scale: Option<f64>,
+    /// Transform, see [`parse_transform`]
+    #[serde(deserialize_with = "deserialize_transform")]
+    pub transform: Option<Transform>,
+}
+
+impl OutputConfig {
+    /// Returns these settings, with the unset ones taken from `fallback`
+    pub fn or(self, fallback: OutputConfig) -> OutputConfig {
+        OutputConfig {
+            mode: self.mode.or(fallback.mode),
+            scale: self.scale.or(fallback.scale),
+            transform: self.transform.or(fallback.transform),
+        }
+    }
+}
+
+/// Where new windows are placed, on the output under the pointer.
+///
+/// Dialogs are always centred over the window they belong to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// In the centre of the output
+    Center,
+    /// Below and to the right of the top-most window
+    Cascade,
+    /// Where they overlap the other windows the least
+    #[default]
+    Smart,
+}
+
+/// Settings applied to the windows matching every pattern of the rule.
+///
+/// Patterns match whole values, and `*` matches any sequence of characters. The app ID is only
+/// set for wayland windows, and the class and instance for X11 ones.
+///
+/// Rules are evaluated again when the title or app ID of a window changes, but the settings
+/// about where and how windows are shown only apply before they are first shown.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct WindowRule {
+    pub app_id: Option<String>,
+    pub title: Option<String>,
+    pub class: Option<String>,
+    pub instance: Option<String>,
+
+    /// Workspace the window opens on, starting from 1
+    pub workspace: Option<usize>,
+    /// Name of the output the window opens on, instead of the one under the pointer
+    pub output: Option<String>,
+    /// Whether the window is left out of the tiling layout
+    pub floating: Option<bool>,
+    /// Initial size, as `[width, height]`
+    pub size: Option<(i32, i32)>,
+    /// Initial location, relative to the top-left corner of the output, as `[x, y]`
+    pub position: Option<(i32, i32)>,
+    pub fullscreen: Option<bool>,
+    /// Opacity of the window, from 0 for invisible to 1 for opaque
+    pub opacity: Option<f32>,
+    /// Whether the window never gets the keyboard focus
+    pub never_focus: Option<bool>,
+}
+
+/// Snapping of the windows moved with the pointer
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SnappingConfig {
+    /// Distance under which the edges of a window snap to the edges of the outputs and of the
+    /// other windows, in logical pixels, 0 disables snapping
+    pub threshold: i32,
+    /// Distance a window has to be dragged past the edge of an output to cross it, in logical
+    /// pixels, 0 disables the resistance
+    pub edge_resistance: i32,
+    /// Whether dropping a window with the pointer on an edge of an output makes it take half of
+    /// the output, or a quarter in the corners
+    pub edge_tiling: bool,
+    /// Colour of the area a window takes when dropped on an edge
+    pub preview_color: Color,
+}
+
+impl Default for SnappingConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 16,
+            edge_resistance: 32,
+            edge_tiling: true,
+            preview_color: Color([0.3, 0.5, 0.8, 0.4]),
+        }
+    }
+}
+
+/// Window switcher, listing the windows from the most recently focused one
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct SwitcherConfig {
+    /// TrueType or OpenType font of the window titles, the sans-serif font of fontconfig if not set
+    pub font: Option<PathBuf>,
+    /// Size of the window titles, in logical pixels
+    pub font_size: f32,
+}
+
+impl Default for SwitcherConfig {
+    fn default() -> Self {
+        Self {
+            font: None,
+            font_size: 16.0,
+        }
+    }
+}
+
+/// Tiling of the windows of each workspace
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TilingConfig {
+    /// Layout used at startup, it can then be changed with a binding
+    pub layout: Layout,
+    /// Part of the width of the output taken by the master window, between 0 and 1
+    pub master_ratio: f64,
+    /// Space around and between the tiled windows, in logical pixels
+    pub gap: i32,
+}
+
+impl Default for TilingConfig {
+    fn default() -> Self {
+        Self {
+            layout: Layout::default(),
+            master_ratio: 0.5,
+            gap: 0,
+        }
+    }
+}
+
+/// How the windows of a workspace are arranged.
+///
+/// Dialogs, and windows made floating, are never tiled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// Windows are moved and resized by hand
+    #[default]
+    Floating,
+    /// The first window takes the left of the output, the others are stacked on the right
+    MasterStack,
+    /// Each window takes half of the space left by the previous one, split along its longest
+    /// side
+    BinarySplit,
+}
+
+impl Layout {
+    /// The layout after this one, cycling back to [`Layout::Floating`]
+    pub fn next(self) -> Layout {
+        match self {
+            Layout::Floating => Layout::MasterStack,
+            Layout::MasterStack => Layout::BinarySplit,
+            Layout::BinarySplit => Layout::Floating,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputMode {
+    pub size: Size<i32, Physical>,
+    /// Refresh rate in mHz, the backend's default is used if `None`
+    pub refresh: Option<i32>,
+}
+
+/// A RGBA colour, written as `#rrggbb` or `#rrggbbaa`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub [f32; 4]);
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "Unable to read {}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => {
+                write!(f, "Invalid configuration in {}: {}", path.display(), err)
+            }
+            ConfigError::Invalid(path, err) => {
+                write!(f, "Invalid configuration in {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads and validates the configuration file at `path`
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let content =
+            fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_owned(), err))?;
+        let config: Config =
+            toml::from_str(&content).map_err(|err| ConfigError::Parse(path.to_owned(), err))?;
+        config
+            .validate()
+            .map_err(|err| ConfigError::Invalid(path.to_owned(), err))?;
+        Ok(config)
+    }
+
+    /// Path of the configuration file used when none is given:
+    /// `$XDG_CONFIG_HOME/thing/config.toml`, or `~/.config/thing/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(config_dir.join("thing").join("config.toml"))
+    }
+
+    /// Settings of the output named `name`
+    pub fn output(&self, name: &str) -> OutputConfig {
+        let output = self.outputs.get(name).cloned().unwrap_or_default();
+        output.or(self.output.clone())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.keyboard.repeat_delay < 0 {
+            return Err("`keyboard.repeat-delay` cannot be negative".into());
+        }
+        if self.keyboard.repeat_rate < 0 {
+            return Err("`keyboard.repeat-rate` cannot be negative".into());
+        }
+
+        let exit = self
+            .keybindings
+            .0
+            .iter()
+            .find(|(c, _)| c.is_emergency_exit());
+        if let Some((combo, _)) = exit {
+            return Err(format!(
+                "`{}` always stops the compositor, and cannot be bound",
+                combo
+            ));
+        }
+
+        if self.workspaces == 0 {
+            return Err("`workspaces` must be at least 1".into());
+        }
+
+        let ratio = self.tiling.master_ratio;
+        if !ratio.is_finite() || ratio <= 0.0 || ratio >= 1.0 {
+            return Err("`tiling.master-ratio` must be between 0 and 1".into());
+        }
+        if self.tiling.gap < 0 {
+            return Err("`tiling.gap` cannot be negative".into());
+        }
+
+        if self.snapping.threshold < 0 {
+            return Err("`snapping.threshold` cannot be negative".into());
+        }
+        if self.snapping.edge_resistance < 0 {
+            return Err("`snapping.edge-resistance` cannot be negative".into());
+        }
+
+        for (i, rule) in self.rules.iter().enumerate() {
+            if rule
+                .workspace
+                .is_some_and(|n| n == 0 || n > self.workspaces)
+            {
+                return Err(format!(
+                    "`rules[{}].workspace` must be between 1 and {}",
+                    i, self.workspaces
+                ));
+            }
+            if rule.size.is_some_and(|(w, h)| w <= 0 || h <= 0) {
+                return Err(format!("`rules[{}].size` must be positive", i));
+            }
+            if rule
+                .opacity
+                .is_some_and(|opacity| !(0.0..=1.0).contains(&opacity))
+            {
+                return Err(format!("`rules[{}].opacity` must be between 0 and 1", i));
+            }
+        }
+
+        if !self.switcher.font_size.is_finite() || self.switcher.font_size <= 0.0 {
+            return Err("`switcher.font-size` must be a positive number".into());
+        }
+
+        let outputs = std::iter::once(("output".to_string(), &self.output)).chain(
+            self.outputs
+                .iter()
+                .map(|(name, output)| (format!("outputs.{}", name), output)),
+        );
+        for (section, output) in outputs {
+            if let Some(scale) = output.scale {
+                if !scale.is_finite() || scale <= 0.0 {
+                    return Err(format!("`{}.scale` must be a positive number", section));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses an output transform: `normal`, `90`, `180`, `270`, `flipped`, `flipped-90`,
+/// `flipped-180` or `flipped-270`.
+///
+/// Rotations are counter-clockwise.
+pub fn parse_transform(s: &str) -> Result<Transform, String> {
+    match s.trim() {
+        "normal" | "0" => Ok(Transform::Normal),
+        "90" => Ok(Transform::_90),
+        "180" => Ok(Transform::_180),
+        "270" => Ok(Transform::_270),
+        "flipped" => Ok(Transform::Flipped),
+        "flipped-90" => Ok(Transform::Flipped90),
+        "flipped-180" => Ok(Transform::Flipped180),
+        "flipped-270" => Ok(Transform::Flipped270),
+        _ => Err(format!("`{}` is not a valid transform", s)),
+    }
+}
+
+/// Parses a size, as `WIDTHxHEIGHT`
+pub fn parse_size(s: &str) -> Result<Size<i32, Physical>, String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("`{}` is not in the `WIDTHxHEIGHT` format", s))?;
+    let parse = |v: &str| match v.trim().parse::<i32>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err(format!("`{}` is not a valid size", v)),
+    };
+    Ok((parse(w)?, parse(h)?).into())
+}
+
+/// Parses an output mode, as `WIDTHxHEIGHT` or `WIDTHxHEIGHT@REFRESH` with the refresh rate in Hz
+pub fn parse_mode(s: &str) -> Result<OutputMode, String> {
+    let (size, refresh) = match s.split_once('@') {
+        Some((size, refresh)) => (size, Some(refresh)),
+        None => (s, None),
+    };
+    let refresh = refresh
+        .map(|refresh| match refresh.trim().parse::<f64>() {
+            Ok(hz) if hz.is_finite() && hz > 0.0 => Ok((hz * 1000.0).round() as i32),
+            _ => Err(format!("`{}` is not a valid refresh rate", refresh)),
+        })
+        .transpose()?;
+    Ok(OutputMode {
+        size: parse_size(size)?,
+        refresh,
+    })
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    let invalid = || {
+        format!(
+            "`{}` is not a colour in the `#rrggbb` or `#rrggbbaa` format",
+            s
+        )
+    };
+
+    let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut color = [1.0; 4];
+    for (i, component) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        let value = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        *component = value as f32 / 255.0;
+    }
+    Ok(Color(color))
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_color(&s).map_err(de::Error::custom)
+    }
+}
+
+fn deserialize_mode<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<OutputMode>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_mode(&s).map(Some).map_err(de::Error::custom)
+}
+
+fn deserialize_transform<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Transform>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_transform(&s).map(Some).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses and validates a configuration file made of `content`
+    fn parse(content: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(content).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn empty_file_gives_defaults() {
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(parse("unknown = 1").is_err());
+        assert!(parse("[keyboard]\nrepeat = 1").is_err());
+    }
+
+    #[test]
+    fn negative_repeat_is_rejected() {
+        assert!(parse("[keyboard]\nrepeat-delay = -1").is_err());
+        assert!(parse("[keyboard]\nrepeat-rate = -1").is_err());
+        assert!(parse("[keyboard]\nrepeat-delay = 0\nrepeat-rate = 0").is_ok());
+    }
+
+    #[test]
+    fn emergency_exit_cannot_be_bound() {
+        assert!(parse("[keybindings]\n\"Ctrl+Alt+BackSpace\" = \"close-window\"").is_err());
+        assert!(parse("[keybindings]\n\"Ctrl+BackSpace\" = \"close-window\"").is_ok());
+    }
+
+    #[test]
+    fn output_scale_must_be_positive() {
+        assert!(parse("[output]\nscale = 0.0").is_err());
+        assert!(parse("[outputs.HDMI-A-1]\nscale = -2.0").is_err());
+        assert!(parse("[output]\nscale = 1.5").is_ok());
+    }
+
+    #[test]
+    fn output_settings_fall_back_on_every_output() {
+        let config = parse(
+            "[output]\nscale = 2.0\ntransform = \"90\"\n\n[outputs.DP-1]\nmode = \"1920x1080@60\"",
+        )
+        .unwrap();
+        let output = config.output("DP-1");
+        assert_eq!(output.scale, Some(2.0));
+        assert_eq!(output.transform, Some(Transform::_90));
+        assert_eq!(
+            output.mode,
+            Some(OutputMode {
+                size: (1920, 1080).into(),
+                refresh: Some(60_000),
+            })
+        );
+        assert_eq!(config.output("DP-2").mode, None);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#ff0000"), Ok(Color([1.0, 0.0, 0.0, 1.0])));
+        assert_eq!(parse_color("#00000000"), Ok(Color([0.0; 4])));
+        assert!(parse_color("ff0000").is_err());
+        assert!(parse_color("#ff00").is_err());
+        assert!(parse_color("#gg0000").is_err());
+    }
+}
@@ -0,0 +1,454 @@
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer};
+use smithay::input::keyboard::{keysyms, xkb, Keysym, ModifiersState};
+
+bitflags::bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct Modifiers: u8 {
+        const CTRL  = 0b0001;
+        const ALT   = 0b0010;
+        const SHIFT = 0b0100;
+        const SUPER = 0b1000;
+    }
+}
+
+impl From<&ModifiersState> for Modifiers {
+    fn from(state: &ModifiersState) -> Self {
+        let mut modifiers = Modifiers::empty();
+        modifiers.set(Modifiers::CTRL, state.ctrl);
+        modifiers.set(Modifiers::ALT, state.alt);
+        modifiers.set(Modifiers::SHIFT, state.shift);
+        modifiers.set(Modifiers::SUPER, state.logo);
+        modifiers
+    }
+}
+
+/// A key combination, written as modifiers and a key name separated by `+` (e.g. `Super+Shift+Q`).
+///
+/// Key names are XKB keysym names, matched case-insensitively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub modifiers: Modifiers,
+    pub keysym: Keysym,
+}
+
+/// Splits a combination written as modifiers and a name separated by `+`
+fn parse_combo(s: &str) -> Result<(Modifiers, &str), String> {
+    let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+    let name = parts.pop().filter(|name| !name.is_empty());
+    let Some(name) = name else {
+        return Err(format!("`{}` has no key or button", s));
+    };
+
+    let mut modifiers = Modifiers::empty();
+    for modifier in parts {
+        modifiers |= match modifier.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CTRL,
+            "alt" => Modifiers::ALT,
+            "shift" => Modifiers::SHIFT,
+            "super" | "logo" | "mod4" => Modifiers::SUPER,
+            _ => return Err(format!("`{}` is not a modifier", modifier)),
+        };
+    }
+    Ok((modifiers, name))
+}
+
+impl KeyCombo {
+    pub fn parse(s: &str) -> Result<KeyCombo, String> {
+        let (modifiers, key) = parse_combo(s)?;
+        let keysym = xkb::keysym_from_name(key, xkb::KEYSYM_CASE_INSENSITIVE);
+        if keysym.raw() == keysyms::KEY_NoSymbol {
+            return Err(format!("`{}` is not a key name", key));
+        }
+
+        Ok(KeyCombo { modifiers, keysym })
+    }
+
+    /// Whether this is `Ctrl+Alt+BackSpace`, with any other modifier, which always stops the
+    /// compositor
+    pub fn is_emergency_exit(&self) -> bool {
+        self.modifiers.contains(Modifiers::CTRL | Modifiers::ALT)
+            && self.keysym == Keysym::BackSpace
+    }
+
+    /// Whether this combination is pressed, given the held modifiers and the unmodified keysyms of
+    /// the pressed key
+    pub fn matches(&self, modifiers: Modifiers, keysyms: &[Keysym]) -> bool {
+        self.modifiers == modifiers && keysyms.contains(&self.keysym)
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, modifier) in [
+            ("Ctrl", Modifiers::CTRL),
+            ("Alt", Modifiers::ALT),
+            ("Shift", Modifiers::SHIFT),
+            ("Super", Modifiers::SUPER),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", xkb::keysym_get_name(self.keysym))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+    Side,
+    Extra,
+}
+
+impl Button {
+    fn from_name(name: &str) -> Option<Button> {
+        match name.to_ascii_lowercase().as_str() {
+            "left" => Some(Button::Left),
+            "right" => Some(Button::Right),
+            "middle" => Some(Button::Middle),
+            "side" => Some(Button::Side),
+            "extra" => Some(Button::Extra),
+            _ => None,
+        }
+    }
+
+    /// Linux input event code of the button
+    pub fn code(&self) -> u32 {
+        match self {
+            Button::Left => 0x110,
+            Button::Right => 0x111,
+            Button::Middle => 0x112,
+            Button::Side => 0x113,
+            Button::Extra => 0x114,
+        }
+    }
+}
+
+/// A pointer button pressed with modifiers, written like key combinations (e.g. `Super+Left`).
+///
+/// Buttons are `Left`, `Right`, `Middle`, `Side` and `Extra`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseCombo {
+    pub modifiers: Modifiers,
+    pub button: Button,
+}
+
+impl MouseCombo {
+    pub fn parse(s: &str) -> Result<MouseCombo, String> {
+        let (modifiers, button) = parse_combo(s)?;
+        let button =
+            Button::from_name(button).ok_or_else(|| format!("`{}` is not a button", button))?;
+        Ok(MouseCombo { modifiers, button })
+    }
+}
+
+/// Something the compositor does when a binding is triggered.
+///
+/// Actions on a window apply to the focused window for key bindings, and to the window under the
+/// pointer for mouse bindings.
+///
+/// Written as a string for actions without argument (e.g. `"close-window"`), or as a table for
+/// the others (e.g. `{ spawn = "foot" }`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Asks the window to close
+    CloseWindow,
+    /// Puts the window above the others
+    RaiseWindow,
+    /// Puts the window below the others
+    LowerWindow,
+    /// Maximizes the window, or gives it back its previous size if it is maximized
+    MaximizeWindow,
+    /// Makes the window fullscreen, or leaves fullscreen
+    FullscreenWindow,
+    /// Hides the window, until it is restored
+    MinimizeWindow,
+    /// Shows the window minimized last
+    RestoreWindow,
+    /// Takes the window out of the tiling layout, or puts it back in
+    ToggleFloating,
+    /// Switches to the next tiling layout, on every output
+    NextTilingLayout,
+    /// Focuses the next window
+    FocusNext,
+    /// Focuses the previous window
+    FocusPrevious,
+    /// Opens the window switcher, or selects the next window in it.
+    ///
+    /// The selected window is focused when the modifiers of the binding are released.
+    SwitchWindows,
+    /// Like [`Action::SwitchWindows`], going through the windows in the reverse order
+    SwitchWindowsBackward,
+    /// Moves the window with the pointer, until the button of the binding is released, or a button
+    /// is clicked for key bindings
+    MoveWindow,
+    /// Resizes the window with the pointer, like [`Action::MoveWindow`], from the edge or corner
+    /// nearest to the pointer
+    ResizeWindow,
+    /// Moves the window with the arrow keys, holding Shift for bigger steps, until Enter is
+    /// pressed, or Escape to put it back
+    MoveWindowWithKeys,
+    /// Resizes the window like [`Action::MoveWindowWithKeys`], from the bottom-right corner, or the
+    /// top-left one while Ctrl is held
+    ResizeWindowWithKeys,
+    /// Runs a command with `sh -c`
+    Spawn(String),
+    /// Switches to the workspace with the given number, starting from 1, on the output of the
+    /// focused window
+    SwitchWorkspace(usize),
+    /// Moves the window to the workspace with the given number of its output
+    MoveToWorkspace(usize),
+    /// Switches to the next keyboard layout
+    NextLayout,
+    /// Stops the compositor
+    Quit,
+    /// Reloads the configuration file
+    ReloadConfig,
+}
+
+impl Action {
+    /// Whether this action applies to a window, and does nothing without one
+    pub fn needs_window(&self) -> bool {
+        matches!(
+            self,
+            Action::CloseWindow
+                | Action::RaiseWindow
+                | Action::LowerWindow
+                | Action::MaximizeWindow
+                | Action::FullscreenWindow
+                | Action::MinimizeWindow
+                | Action::ToggleFloating
+                | Action::MoveWindow
+                | Action::ResizeWindow
+                | Action::MoveWindowWithKeys
+                | Action::ResizeWindowWithKeys
+                | Action::MoveToWorkspace(_)
+        )
+    }
+}
+
+/// Key bindings, written as a table of key combinations and actions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keybindings(pub Vec<(KeyCombo, Action)>);
+
+impl Keybindings {
+    /// Action bound to the pressed key, if any
+    pub fn action(&self, modifiers: Modifiers, keysyms: &[Keysym]) -> Option<&Action> {
+        self.0
+            .iter()
+            .find(|(combo, _)| combo.matches(modifiers, keysyms))
+            .map(|(_, action)| action)
+    }
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let bindings = [
+            ("Alt+F4", Action::CloseWindow),
+            ("Alt+F7", Action::MoveWindowWithKeys),
+            ("Alt+F8", Action::ResizeWindowWithKeys),
+            ("Alt+F9", Action::MinimizeWindow),
+            ("Alt+Shift+F9", Action::RestoreWindow),
+            ("Alt+F10", Action::MaximizeWindow),
+            ("Alt+F11", Action::FullscreenWindow),
+            ("Alt+Space", Action::NextTilingLayout),
+            ("Alt+Shift+Space", Action::ToggleFloating),
+            ("Alt+J", Action::FocusNext),
+            ("Alt+K", Action::FocusPrevious),
+            ("Alt+Tab", Action::SwitchWindows),
+            ("Alt+Shift+Tab", Action::SwitchWindowsBackward),
+            ("Alt+Shift+R", Action::ReloadConfig),
+            ("Alt+Shift+E", Action::Quit),
+        ];
+        let workspaces = (1..=9).flat_map(|n| {
+            [
+                (format!("Alt+{}", n), Action::SwitchWorkspace(n)),
+                (format!("Alt+Shift+{}", n), Action::MoveToWorkspace(n)),
+            ]
+        });
+        Keybindings(
+            bindings
+                .into_iter()
+                .map(|(combo, action)| (combo.to_string(), action))
+                .chain(workspaces)
+                .map(|(combo, action)| (KeyCombo::parse(&combo).unwrap(), action))
+                .collect(),
+        )
+    }
+}
+
+/// Mouse bindings, written as a table of button combinations and actions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mousebindings(pub Vec<(MouseCombo, Action)>);
+
+impl Mousebindings {
+    /// Action bound to the pressed button, if any
+    pub fn action(&self, modifiers: Modifiers, button: u32) -> Option<&Action> {
+        self.0
+            .iter()
+            .find(|(combo, _)| combo.modifiers == modifiers && combo.button.code() == button)
+            .map(|(_, action)| action)
+    }
+}
+
+impl Default for Mousebindings {
+    fn default() -> Self {
+        let bindings = [
+            ("Alt+Left", Action::MoveWindow),
+            ("Alt+Right", Action::ResizeWindow),
+        ];
+        Mousebindings(
+            bindings
+                .into_iter()
+                .map(|(combo, action)| (MouseCombo::parse(combo).unwrap(), action))
+                .collect(),
+        )
+    }
+}
+
+/// Deserializes a table of combinations and actions, parsing the combinations with `parse`.
+///
+/// The bindings keep the order of the table, and a combination bound twice, even written
+/// differently, is an error.
+fn deserialize_bindings<'de, D, C>(
+    deserializer: D,
+    parse: fn(&str) -> Result<C, String>,
+) -> Result<Vec<(C, Action)>, D::Error>
+where
+    D: Deserializer<'de>,
+    C: PartialEq,
+{
+    struct BindingsVisitor<C> {
+        parse: fn(&str) -> Result<C, String>,
+    }
+
+    impl<'de, C: PartialEq> de::Visitor<'de> for BindingsVisitor<C> {
+        type Value = Vec<(C, Action)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a table of bindings")
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut bindings: Vec<(C, Action)> = Vec::new();
+            while let Some((name, action)) = map.next_entry::<String, Action>()? {
+                let combo = (self.parse)(&name)
+                    .map_err(|err| de::Error::custom(format!("invalid binding: {}", err)))?;
+                if bindings.iter().any(|(c, _)| *c == combo) {
+                    return Err(de::Error::custom(format!(
+                        "`{}` is bound more than once",
+                        name
+                    )));
+                }
+                bindings.push((combo, action));
+            }
+            Ok(bindings)
+        }
+    }
+
+    deserializer.deserialize_map(BindingsVisitor { parse })
+}
+
+impl<'de> Deserialize<'de> for Keybindings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_bindings(deserializer, KeyCombo::parse).map(Keybindings)
+    }
+}
+
+impl<'de> Deserialize<'de> for Mousebindings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_bindings(deserializer, MouseCombo::parse).map(Mousebindings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keybindings(content: &str) -> Result<Keybindings, String> {
+        toml::from_str(content).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn key_combos() {
+        let combo = KeyCombo::parse("super + shift+q").unwrap();
+        assert_eq!(combo.modifiers, Modifiers::SUPER | Modifiers::SHIFT);
+        assert_eq!(combo.keysym, Keysym::q);
+        assert_eq!(
+            KeyCombo::parse("Ctrl+Return").unwrap().to_string(),
+            "Ctrl+Return"
+        );
+
+        assert!(KeyCombo::parse("Hyper+Q").is_err());
+        assert!(KeyCombo::parse("Alt+").is_err());
+        assert!(KeyCombo::parse("Alt+NotAKey").is_err());
+    }
+
+    #[test]
+    fn mouse_combos() {
+        let combo = MouseCombo::parse("Alt+Middle").unwrap();
+        assert_eq!(combo.modifiers, Modifiers::ALT);
+        assert_eq!(combo.button, Button::Middle);
+        assert!(MouseCombo::parse("Alt+Wheel").is_err());
+    }
+
+    #[test]
+    fn bindings_keep_their_order() {
+        let bindings = keybindings(
+            r#"
+            "Alt+Z" = "quit"
+            "Alt+A" = { spawn = "foot" }
+            "Alt+M" = "close-window"
+            "#,
+        )
+        .unwrap();
+        let actions: Vec<&Action> = bindings.0.iter().map(|(_, action)| action).collect();
+        assert_eq!(
+            actions,
+            [
+                &Action::Quit,
+                &Action::Spawn("foot".into()),
+                &Action::CloseWindow
+            ]
+        );
+    }
+
+    #[test]
+    fn combos_bound_twice_are_rejected() {
+        assert!(keybindings(
+            r#"
+            "Alt+Shift+q" = "quit"
+            "shift+alt+Q" = "close-window"
+            "#,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn emergency_exit() {
+        assert!(KeyCombo::parse("Ctrl+Alt+BackSpace")
+            .unwrap()
+            .is_emergency_exit());
+        assert!(KeyCombo::parse("Ctrl+Alt+Shift+backspace")
+            .unwrap()
+            .is_emergency_exit());
+        assert!(!KeyCombo::parse("Alt+BackSpace")
+            .unwrap()
+            .is_emergency_exit());
+    }
+
+    #[test]
+    fn bound_actions_are_found() {
+        let bindings = Keybindings::default();
+        assert_eq!(
+            bindings.action(Modifiers::ALT, &[Keysym::F4]),
+            Some(&Action::CloseWindow)
+        );
+        assert_eq!(bindings.action(Modifiers::CTRL, &[Keysym::F4]), None);
+    }
+}
@@ -1,2 +1,4 @@
 pub mod state;
 pub mod backend;
+pub mod config;
+pub mod render;
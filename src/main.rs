@@ -1,37 +1,114 @@
+use std::process::ExitCode;
+
+use clap::Parser;
 use smithay::reexports::{calloop::EventLoop, wayland_server::Display};
-use tracing::info;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
 use wayland_compositor_thing::{
-    backend::{self, CalloopData},
-    state::ThingState,
+    backend::{self, BackendOptions, CalloopData},
+    config::{Config, OutputConfig, OutputMode},
+    state::{StartupCommand, StateOptions, ThingState},
 };
 
-fn main() {
-    if let Ok(env_filter) = tracing_subscriber::EnvFilter::try_from_default_env() {
-        tracing_subscriber::fmt()
-            .compact()
-            .with_env_filter(env_filter)
-            .init();
-    } else {
-        tracing_subscriber::fmt().compact().init();
+use crate::cli::{Cli, LogFormat};
+
+mod cli;
+
+fn init_logging(cli: &Cli) {
+    let env_filter = match &cli.log_level {
+        Some(level) => EnvFilter::try_new(level)
+            .map_err(|err| eprintln!("Invalid log level `{}`: {}", level, err))
+            .ok(),
+        None => EnvFilter::try_from_default_env().ok(),
     }
+    .unwrap_or_else(|| EnvFilter::new("info"));
+
+    let builder = tracing_subscriber::fmt().with_env_filter(env_filter);
+    match cli.log_format {
+        LogFormat::Compact => builder.compact().init(),
+        LogFormat::Full => builder.init(),
+        LogFormat::Pretty => builder.pretty().init(),
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logging(&cli);
+
+    let config_path = cli.config.clone().or_else(Config::default_path);
+    let config = match &config_path {
+        // Only a configuration file given explicitly has to exist
+        Some(path) if cli.config.is_some() || path.exists() => {
+            Config::load(path).unwrap_or_else(|err| {
+                error!("{}, using the default configuration", err);
+                Config::default()
+            })
+        }
+        _ => Config::default(),
+    };
 
     let mut event_loop: EventLoop<CalloopData> =
         EventLoop::try_new().expect("unable to initialize event loop");
     let display: Display<ThingState> = Display::new().expect("unable to initialize display");
     let dh = display.handle();
 
-    let state = ThingState::new(event_loop.handle(), display);
+    let options = StateOptions {
+        socket_name: cli.socket.clone(),
+        xwayland: !cli.no_xwayland,
+        startup_command: cli.spawn.clone().map(|command| StartupCommand {
+            command,
+            wait_for_xwayland: cli.wait_for_xwayland,
+            exit_with: cli.exit_with_spawn,
+        }),
+        config,
+        config_path,
+        output_overrides: OutputConfig {
+            mode: cli.output_size.map(|size| OutputMode {
+                size,
+                refresh: None,
+            }),
+            scale: cli.scale,
+            transform: cli.transform,
+        },
+    };
+    let state = ThingState::new(event_loop.handle(), event_loop.get_signal(), display, options);
 
     let mut data = CalloopData { state, dh };
 
-    //TODO: Auto-detect backend
-    backend::winit::run(&mut event_loop, &mut data).unwrap();
+    let backend_options = BackendOptions {
+        output_count: cli.outputs,
+        dump_frames: cli.dump_frames.clone(),
+    };
+    if let Err(err) = backend::start(
+        &mut event_loop,
+        &mut data,
+        cli.backend.as_deref(),
+        &backend_options,
+    ) {
+        error!("{}", err);
+        return ExitCode::FAILURE;
+    }
+
+    data.state.watch_config();
+    data.state.run_startup_command();
+    if let Some(code) = data.state.exit_code {
+        // The startup command could not be spawned
+        return ExitCode::from(code.clamp(0, 255) as u8);
+    }
 
     event_loop
-        .run(None, &mut data, move |_| {
-            // Smallvil is running
+        .run(None, &mut data, move |data| {
+            // Replies to requests which did not damage anything are not flushed by a redraw
+            if let Err(err) = data.dh.flush_clients() {
+                error!(?err, "Error when flushing clients");
+            }
         })
         .expect("Unable to start event loop");
 
     info!("compositor closed");
+
+    match data.state.exit_code {
+        Some(code) => ExitCode::from(code.clamp(0, 255) as u8),
+        None => ExitCode::SUCCESS,
+    }
 }
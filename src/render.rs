@@ -1,14 +1,26 @@
 use smithay::{
-    backend::renderer::{element::texture::TextureRenderElement, ImportAll, ImportMem, Renderer},
+    backend::renderer::{
+        element::{
+            memory::MemoryRenderBufferRenderElement, solid::SolidColorRenderElement,
+            surface::WaylandSurfaceRenderElement, AsRenderElements,
+        },
+        ImportAll, ImportMem, Renderer,
+    },
+    desktop::Window,
+    output::Output,
     render_elements,
+    utils::Scale,
 };
 
-use crate::state::elements::WindowRenderElement;
+use crate::state::ThingState;
 
 render_elements! {
     pub OutputRenderElements<R> where R: ImportAll + ImportMem;
-    Window = WindowRenderElement<R>,
-    DebugUi = TextureRenderElement<<R as Renderer>::TextureId>,
+    Window = WaylandSurfaceRenderElement<R>,
+    // Drawn by the compositor above the windows, like the window switcher
+    Overlay = MemoryRenderBufferRenderElement<R>,
+    // Area a window dragged to an edge takes once dropped
+    Preview = SolidColorRenderElement,
 }
 
 impl<R: Renderer + std::fmt::Debug> std::fmt::Debug for OutputRenderElements<R>
@@ -18,8 +30,81 @@ where
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Window(arg0) => f.debug_tuple("Window").field(arg0).finish(),
-            Self::DebugUi(arg0) => f.debug_tuple("DebugUi").field(arg0).finish(),
+            Self::Overlay(arg0) => f.debug_tuple("Overlay").field(arg0).finish(),
+            Self::Preview(arg0) => f.debug_tuple("Preview").field(arg0).finish(),
             Self::_GenericCatcher(arg0) => f.debug_tuple("_GenericCatcher").field(arg0).finish(),
         }
     }
 }
+
+/// Elements of `output`, from the top-most one: the ones drawn by the compositor, then the
+/// windows
+pub fn output_elements<R>(
+    state: &ThingState,
+    renderer: &mut R,
+    output: &Output,
+) -> Vec<OutputRenderElements<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    <R as Renderer>::TextureId: Send + Clone + 'static,
+{
+    let mut elements = overlay_elements(state, renderer, output);
+    elements.extend(window_elements(state, renderer, output));
+    elements
+}
+
+/// Elements drawn by the compositor above the windows of `output`
+fn overlay_elements<R>(
+    state: &ThingState,
+    renderer: &mut R,
+    output: &Output,
+) -> Vec<OutputRenderElements<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    <R as Renderer>::TextureId: Send + Clone + 'static,
+{
+    let switcher = state
+        .switcher_element(renderer, output)
+        .map(OutputRenderElements::Overlay);
+    let preview = state
+        .snap_preview_element(output)
+        .map(OutputRenderElements::Preview);
+    switcher.into_iter().chain(preview).collect()
+}
+
+/// Elements of the windows displayed on `output`, from the top-most one, with the opacity given
+/// by the window rules
+fn window_elements<R>(
+    state: &ThingState,
+    renderer: &mut R,
+    output: &Output,
+) -> Vec<OutputRenderElements<R>>
+where
+    R: Renderer + ImportAll,
+    <R as Renderer>::TextureId: Clone + 'static,
+{
+    let Some(output_geo) = state.space.output_geometry(output) else {
+        return Vec::new();
+    };
+    let scale = Scale::from(output.current_scale().fractional_scale());
+
+    let windows: Vec<&Window> = state.space.elements_for_output(output).collect();
+    windows
+        .into_iter()
+        .rev()
+        .flat_map(|window| {
+            // Locations in the space are the ones of the window geometries, the buffer starts
+            // before them by the size of the client-side decorations
+            let loc = state.space.element_location(window).unwrap_or_default()
+                - window.geometry().loc
+                - output_geo.loc;
+            window.render_elements::<WaylandSurfaceRenderElement<R>>(
+                renderer,
+                loc.to_physical_precise_round(scale),
+                scale,
+                state.window_opacity(window),
+            )
+        })
+        .map(OutputRenderElements::Window)
+        .collect()
+}
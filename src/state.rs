@@ -1,11 +1,23 @@
-use std::{ffi::OsString, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    path::PathBuf,
+    process::{Child, Command},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use smithay::{
     delegate_data_device, delegate_output, delegate_seat,
     desktop::{PopupManager, Space, Window, WindowSurfaceType},
-    input::{keyboard::KeyboardHandle, pointer::PointerHandle, Seat, SeatHandler, SeatState},
+    output::Output,
+    input::{
+        keyboard::{KeyboardHandle, Keycode},
+        pointer::PointerHandle,
+        Seat, SeatHandler, SeatState,
+    },
     reexports::{
-        calloop::{generic::Generic, Interest, LoopHandle, Mode, PostAction},
+        calloop::{generic::Generic, Interest, LoopHandle, LoopSignal, Mode, PostAction},
         wayland_server::{
             backend::{ClientData, ClientId, DisconnectReason},
             protocol::wl_surface::WlSurface,
@@ -14,7 +26,8 @@ use smithay::{
     },
     utils::{Clock, Logical, Monotonic, Point},
     wayland::{
-        compositor::{CompositorClientState, CompositorState},
+        compositor::{send_surface_state, CompositorClientState, CompositorState, SurfaceData},
+        fractional_scale::{with_fractional_scale, FractionalScaleManagerState},
         output::{OutputHandler, OutputManagerState},
         seat::WaylandFocus,
         selection::{
@@ -26,26 +39,86 @@ use smithay::{
         shell::xdg::XdgShellState,
         shm::ShmState,
         socket::ListeningSocketSource,
+        viewporter::ViewporterState,
         xwayland_shell::XWaylandShellState,
     },
     xwayland::X11Wm,
 };
 use tracing::{error, info};
 
-use crate::backend::CalloopData;
+use crate::{
+    backend::{scheduler::RenderScheduler, CalloopData},
+    config::{Config, Layout, OutputConfig},
+};
 
+mod actions;
 mod compositor;
+mod config;
 mod elements;
+mod focus;
 mod input;
+mod keycodes;
+mod placement;
+mod rules;
+mod snapping;
+mod startup;
+mod switcher;
+mod tiling;
+mod window;
+mod workspace;
 mod xdg_shell;
 mod xwayland;
 
+pub use actions::close_window;
+pub use startup::StartupCommand;
+pub use workspace::WORKSPACES_ENV;
+
+/// Options used when creating the compositor state
+#[derive(Debug, Clone)]
+pub struct StateOptions {
+    /// Name of the wayland socket, picked automatically if `None`
+    pub socket_name: Option<String>,
+    /// Whether XWayland should be started
+    pub xwayland: bool,
+    /// Command to run once the compositor is ready
+    pub startup_command: Option<StartupCommand>,
+    /// Initial configuration
+    pub config: Config,
+    /// Path of the configuration file, watched for changes if set
+    pub config_path: Option<PathBuf>,
+    /// Output settings given on the command line, taking precedence over the configuration
+    pub output_overrides: OutputConfig,
+}
+
+impl Default for StateOptions {
+    fn default() -> Self {
+        Self {
+            socket_name: None,
+            xwayland: true,
+            startup_command: None,
+            config: Config::default(),
+            config_path: None,
+            output_overrides: OutputConfig::default(),
+        }
+    }
+}
+
 pub struct ThingState {
     pub loop_handle: LoopHandle<'static, CalloopData>,
+    pub loop_signal: LoopSignal,
     pub start_time: Instant,
     pub socket_name: OsString,
+    /// File describing the workspaces for panels, next to the wayland socket
+    pub workspaces_path: Option<PathBuf>,
+    /// Windows, at the location of their geometry, which excludes client-side decorations
     pub space: Space<Window>,
     pub clock: Clock<Monotonic>,
+    pub render_scheduler: RenderScheduler,
+    pub config: Config,
+    pub config_path: Option<PathBuf>,
+    /// Watcher of the configuration file, stopped when dropped
+    pub config_watcher: Option<notify::RecommendedWatcher>,
+    pub output_overrides: OutputConfig,
 
     // Smithay
     pub compositor_state: CompositorState,
@@ -53,23 +126,59 @@ pub struct ThingState {
     pub shm_state: ShmState,
     pub output_manager_state: OutputManagerState,
     pub data_device_state: DataDeviceState,
+    pub fractional_scale_manager_state: FractionalScaleManagerState,
+    pub viewporter_state: ViewporterState,
     pub seat_state: SeatState<ThingState>,
     pub seat: Seat<ThingState>,
     // temporary, there is probably a better way to do this
     pub keyboard_handle: KeyboardHandle<ThingState>,
     pub pointer_handle: PointerHandle<ThingState>,
+    /// Keys which triggered a binding, their release is not sent to clients
+    pub suppressed_keys: Vec<Keycode>,
+    /// Pointer buttons which triggered a binding, their release is not sent to clients
+    pub suppressed_buttons: Vec<u32>,
     pub popup_manager: PopupManager,
+    /// Windows whose size is not known yet, placed once they have a buffer
+    pub unplaced_windows: Vec<Window>,
+    /// X11 windows which did not ask to be mapped yet, with their rules already evaluated
+    pub unmapped_x11_windows: Vec<Window>,
+    /// Windows in the order they were focused, the focused one last
+    pub focus_stack: Vec<Window>,
+    /// Window switcher, while it is open
+    pub switcher: Option<switcher::Switcher>,
+    /// Font of the window switcher, `None` if it could not be loaded, with the configured file it
+    /// was loaded for, `None` for the default font
+    pub switcher_font: Option<(Option<PathBuf>, Option<fontdue::Font>)>,
+    /// Area the window being moved with the pointer takes once dropped, if it is on an edge
+    pub snap_preview: Option<snapping::SnapPreview>,
+    /// Windows hidden from the space with their location, the last one being restored first
+    pub minimized_windows: Vec<(Window, Point<i32, Logical>)>,
+    /// Workspaces of each output, by output name so that they are kept while it is unplugged
+    pub workspaces: HashMap<String, workspace::Workspaces>,
+    /// Current tiling layout, of every output
+    pub layout: Layout,
+    /// Windows in the order they are tiled, the first one being the master window
+    pub tiling_order: Vec<Window>,
 
     // XWayland
     // pub xwayland: Option<XWayland>,
     pub xwm: Option<X11Wm>,
+    /// X11 display number of XWayland, if it was started
+    pub xdisplay: Option<u32>,
+
+    /// Command to run once the compositor is ready, until it gets run
+    pub startup_command: Option<StartupCommand>,
+    /// Exit code of the compositor, set when it is stopped by the startup command exiting
+    pub exit_code: Option<i32>,
     pub xw_shell_state: XWaylandShellState,
 }
 
 impl ThingState {
     pub fn new(
         loop_handle: LoopHandle<'static, CalloopData>,
+        loop_signal: LoopSignal,
         display: Display<ThingState>,
+        options: StateOptions,
     ) -> Self {
         let start_time = Instant::now();
 
@@ -82,17 +191,42 @@ impl ThingState {
         let shm_state = ShmState::new::<ThingState>(dh, vec![]);
         let output_manager_state = OutputManagerState::new_with_xdg_output::<Self>(dh);
         let data_device_state = DataDeviceState::new::<Self>(dh);
+        let fractional_scale_manager_state = FractionalScaleManagerState::new::<Self>(dh);
+        let viewporter_state = ViewporterState::new::<Self>(dh);
 
         let mut seat_state = SeatState::new();
         let mut seat = seat_state.new_wl_seat(dh, "winit");
+        let keyboard_config = &options.config.keyboard;
         let keyboard_handle = seat
-            .add_keyboard(Default::default(), 200, 200)
+            .add_keyboard(
+                keyboard_config.xkb_config(),
+                keyboard_config.repeat_delay,
+                keyboard_config.repeat_rate,
+            )
+            .or_else(|err| {
+                error!(?err, "Invalid keyboard layout, using the default one");
+                seat.add_keyboard(
+                    Default::default(),
+                    keyboard_config.repeat_delay,
+                    keyboard_config.repeat_rate,
+                )
+            })
             .expect("Unable to initialize default keyboard");
         let pointer_handle = seat.add_pointer();
 
         // Creating wayland socket
-        let listening_socket = ListeningSocketSource::new_auto().unwrap();
+        let listening_socket = match &options.socket_name {
+            Some(name) => ListeningSocketSource::with_name(name),
+            None => ListeningSocketSource::new_auto(),
+        }
+        .expect("Unable to create the wayland socket");
         let socket_name = listening_socket.socket_name().to_os_string();
+        info!("Listening on wayland socket {:?}", socket_name);
+        let workspaces_path = std::env::var_os("XDG_RUNTIME_DIR").map(|dir| {
+            let mut name = socket_name.clone();
+            name.push(".workspaces");
+            PathBuf::from(dir).join(name)
+        });
 
         // Insert new client when it connects to the socket
         loop_handle
@@ -125,33 +259,147 @@ impl ThingState {
 
         // XWayland
         // let xwayland = xwayland::setup(&dh, &loop_handle);
-        xwayland::setup(&dh, loop_handle.clone());
+        let xdisplay = options
+            .xwayland
+            .then(|| xwayland::setup(&dh, loop_handle.clone()))
+            .flatten();
         let xw_shell_state = XWaylandShellState::new::<ThingState>(&dh);
 
+        let layout = options.config.tiling.layout;
         ThingState {
             loop_handle,
+            loop_signal,
             start_time,
             space,
             socket_name,
+            workspaces_path,
             clock,
+            render_scheduler: RenderScheduler::default(),
+            config: options.config,
+            config_path: options.config_path,
+            config_watcher: None,
+            output_overrides: options.output_overrides,
 
             compositor_state,
             xdg_shell_state,
             shm_state,
             output_manager_state,
             data_device_state,
+            fractional_scale_manager_state,
+            viewporter_state,
             seat_state,
             seat,
             keyboard_handle,
             pointer_handle,
+            suppressed_keys: Vec::new(),
+            suppressed_buttons: Vec::new(),
             popup_manager: Default::default(),
+            unplaced_windows: Vec::new(),
+            unmapped_x11_windows: Vec::new(),
+            focus_stack: Vec::new(),
+            switcher: None,
+            switcher_font: None,
+            snap_preview: None,
+            minimized_windows: Vec::new(),
+            workspaces: HashMap::new(),
+            layout,
+            tiling_order: Vec::new(),
 
             // xwayland,
             xwm: None,
+            xdisplay,
             xw_shell_state,
+
+            startup_command: options.startup_command,
+            exit_code: None,
         }
     }
 
+    /// Spawns `command` with `sh -c`, as a client of this compositor.
+    ///
+    /// The environment variables pointing to the wayland and X11 displays are only set for the
+    /// spawned process.
+    pub fn spawn(&self, command: &str) -> std::io::Result<Child> {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.arg("-c")
+            .arg(command)
+            .env("WAYLAND_DISPLAY", &self.socket_name);
+        if let Some(path) = &self.workspaces_path {
+            cmd.env(WORKSPACES_ENV, path);
+        }
+        match self.xdisplay {
+            Some(display) => cmd.env("DISPLAY", format!(":{}", display)),
+            None => cmd.env_remove("DISPLAY"),
+        };
+        cmd.spawn()
+    }
+
+    /// Places the outputs side by side from left to right, keeping their current order
+    pub fn arrange_outputs(&mut self) {
+        let mut outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        outputs.sort_by_key(|output| {
+            self.space
+                .output_geometry(output)
+                .map(|geo| geo.loc.x)
+                .unwrap_or_default()
+        });
+
+        let mut x = 0;
+        for output in outputs {
+            output.change_current_state(None, None, None, Some((x, 0).into()));
+            self.space.map_output(&output, (x, 0));
+            self.render_scheduler.queue_redraw(&output);
+
+            x += self
+                .space
+                .output_geometry(&output)
+                .map(|geo| geo.size.w)
+                .unwrap_or_default();
+        }
+        self.retile_all();
+        self.workspaces_changed();
+    }
+
+    /// Queues a redraw of every output
+    pub fn queue_redraw_all(&mut self) {
+        for output in self.space.outputs() {
+            self.render_scheduler.queue_redraw(output);
+        }
+    }
+
+    /// Queues a redraw of the outputs displaying `window`
+    pub fn queue_redraw_for_window(&mut self, window: &Window) {
+        let Some(bbox) = self.space.element_bbox(window) else {
+            return;
+        };
+        for output in self.space.outputs() {
+            let overlaps = self
+                .space
+                .output_geometry(output)
+                .is_some_and(|geo| geo.overlaps(bbox));
+            if overlaps {
+                self.render_scheduler.queue_redraw(output);
+            }
+        }
+    }
+
+    /// Sends frame callbacks to the windows displayed on `output`, once it has been rendered.
+    ///
+    /// The windows for which `output` is the main output are also told its scale and transform.
+    pub fn send_frames(&self, output: &Output) {
+        self.space.elements_for_output(output).for_each(|window| {
+            if self.space.outputs_for_element(window).first() == Some(output) {
+                send_output_state(window, output);
+            }
+            window.send_frame(
+                output,
+                self.start_time.elapsed(),
+                Some(Duration::ZERO),
+                |_, _| Some(output.clone()),
+            )
+        });
+    }
+
     pub fn window_for_surface(&self, surface: WlSurface) -> Option<&Window> {
         self.space.elements().find(|w| {
             w.wl_surface()
@@ -177,6 +425,25 @@ impl ThingState {
     }
 }
 
+/// Sends the preferred scale and transform of `output` to the surfaces of `window`
+pub fn send_output_state(window: &Window, output: &Output) {
+    window.with_surfaces(|surface, states| send_surface_output_state(surface, states, output));
+}
+
+/// Sends the preferred scale and transform of `output` to `surface`
+pub fn send_surface_output_state(surface: &WlSurface, states: &SurfaceData, output: &Output) {
+    let scale = output.current_scale();
+    send_surface_state(
+        surface,
+        states,
+        scale.integer_scale(),
+        output.current_transform(),
+    );
+    with_fractional_scale(states, |fractional_scale| {
+        fractional_scale.set_preferred_scale(scale.fractional_scale());
+    });
+}
+
 #[derive(Default)]
 pub struct ClientState {
     pub compositor_state: CompositorClientState,
@@ -205,6 +472,10 @@ impl SeatHandler for ThingState {
     fn seat_state(&mut self) -> &mut SeatState<Self> {
         &mut self.seat_state
     }
+
+    fn focus_changed(&mut self, _seat: &Seat<Self>, focused: Option<&WlSurface>) {
+        self.on_focus_changed(focused);
+    }
 }
 
 delegate_seat!(ThingState);
@@ -0,0 +1,302 @@
+use std::time::Duration;
+
+use smithay::{
+    desktop::Window,
+    input::{
+        keyboard::GrabStartData as KeyboardGrabStartData,
+        pointer::{Focus, GrabStartData},
+    },
+    utils::{Rectangle, Serial, SERIAL_COUNTER},
+    wayland::seat::WaylandFocus,
+};
+use tracing::{error, info};
+
+use crate::config::bindings::{Action, Button};
+
+use super::{
+    rules::accepts_focus,
+    xdg_shell::{
+        key_grab::MoveResizeKeyboardGrab,
+        move_grab::MovePointerGrab,
+        resize_grab::{ResizeEdge, ResizePointerGrab},
+    },
+    ThingState,
+};
+
+impl ThingState {
+    /// Runs a compositor action triggered by a key binding, window actions apply to the focused
+    /// window
+    pub fn run_action(&mut self, action: Action, serial: Serial) {
+        let window = self.focused_window();
+        // Move and resize grabs end with a click
+        self.run_action_on(action, window, Button::Left.code(), serial);
+    }
+
+    /// Runs a compositor action triggered by a mouse binding, window actions apply to `window`.
+    ///
+    /// Move and resize grabs end when `button` is released.
+    pub fn run_pointer_action(
+        &mut self,
+        action: Action,
+        window: Option<Window>,
+        button: u32,
+        serial: Serial,
+    ) {
+        self.run_action_on(action, window, button, serial);
+    }
+
+    fn run_action_on(
+        &mut self,
+        action: Action,
+        window: Option<Window>,
+        button: u32,
+        serial: Serial,
+    ) {
+        match action {
+            Action::CloseWindow => {
+                if let Some(window) = window {
+                    close_window(&window);
+                }
+            }
+            Action::RaiseWindow => {
+                if let Some(window) = window {
+                    self.space.raise_element(&window, false);
+                    self.queue_redraw_all();
+                }
+            }
+            Action::LowerWindow => {
+                if let Some(window) = window {
+                    self.lower_window(&window);
+                }
+            }
+            Action::MaximizeWindow => {
+                if let Some(window) = window {
+                    self.toggle_maximized(&window);
+                }
+            }
+            Action::FullscreenWindow => {
+                if let Some(window) = window {
+                    self.toggle_fullscreen(&window);
+                }
+            }
+            Action::MinimizeWindow => {
+                if let Some(window) = window {
+                    self.minimize_window(&window);
+                }
+            }
+            Action::RestoreWindow => self.restore_window(serial),
+            Action::ToggleFloating => {
+                if let Some(window) = window {
+                    self.toggle_floating(&window);
+                }
+            }
+            Action::NextTilingLayout => self.set_layout(self.layout.next()),
+            Action::FocusNext => self.focus_next(serial),
+            Action::FocusPrevious => self.focus_previous(serial),
+            Action::SwitchWindows => self.switch_windows(true, serial),
+            Action::SwitchWindowsBackward => self.switch_windows(false, serial),
+            Action::MoveWindow => {
+                if let Some(window) = window {
+                    self.grab_window(window, button, false);
+                }
+            }
+            Action::ResizeWindow => {
+                if let Some(window) = window {
+                    self.grab_window(window, button, true);
+                }
+            }
+            Action::MoveWindowWithKeys => {
+                if let Some(window) = window {
+                    self.grab_window_with_keys(window, false, serial);
+                }
+            }
+            Action::ResizeWindowWithKeys => {
+                if let Some(window) = window {
+                    self.grab_window_with_keys(window, true, serial);
+                }
+            }
+            Action::Spawn(command) => match self.spawn(&command) {
+                Ok(mut child) => {
+                    info!("Spawned `{}`", command);
+                    // Reap the process once it exits
+                    std::thread::spawn(move || child.wait());
+                }
+                Err(err) => error!(?err, "Unable to spawn `{}`", command),
+            },
+            Action::SwitchWorkspace(number) => self.switch_workspace(number),
+            Action::MoveToWorkspace(number) => {
+                if let Some(window) = window {
+                    self.move_to_workspace(&window, number);
+                }
+            }
+            Action::NextLayout => {
+                let keyboard = self.keyboard_handle.clone();
+                keyboard.with_xkb_state(self, |mut context| context.cycle_next_layout());
+            }
+            Action::Quit => self.quit(),
+            Action::ReloadConfig => self.reload_config(),
+        }
+    }
+
+    /// Stops the compositor.
+    ///
+    /// The keyboard and pointer grabs are released first, so clients holding one cannot prevent
+    /// it.
+    pub fn quit(&mut self) {
+        info!("Quitting");
+
+        let time = Duration::from(self.clock.now()).as_millis() as u32;
+        let keyboard = self.keyboard_handle.clone();
+        if keyboard.is_grabbed() {
+            keyboard.unset_grab(self);
+        }
+        let pointer = self.pointer_handle.clone();
+        if pointer.is_grabbed() {
+            pointer.unset_grab(self, SERIAL_COUNTER.next_serial(), time);
+        }
+
+        self.loop_signal.stop();
+        self.loop_signal.wakeup();
+    }
+
+    /// The window having the keyboard focus
+    pub fn focused_window(&self) -> Option<Window> {
+        let focus = self.keyboard_handle.current_focus()?;
+        self.window_for_surface(focus).cloned()
+    }
+
+    /// Raises and focuses `window`.
+    ///
+    /// Windows on other workspaces, or kept from being focused by the rules, are left unfocused.
+    pub fn focus_window(&mut self, window: &Window, serial: Serial) {
+        self.space.raise_element(window, false);
+        if !accepts_focus(window) || self.space.element_location(window).is_none() {
+            self.queue_redraw_all();
+            return;
+        }
+        let keyboard = self.keyboard_handle.clone();
+        keyboard.set_focus(self, window.wl_surface().map(|s| s.into_owned()), serial);
+        self.queue_redraw_all();
+    }
+
+    /// Focuses the bottom-most window, cycling through every window which can be focused when
+    /// repeated
+    fn focus_next(&mut self, serial: Serial) {
+        let Some(window) = self.space.elements().find(|w| accepts_focus(w)).cloned() else {
+            return;
+        };
+        self.focus_window(&window, serial);
+    }
+
+    /// Sends the top-most window to the bottom and focuses the one below it, cycling through every
+    /// window in the reverse order of [`Self::focus_next`] when repeated
+    fn focus_previous(&mut self, serial: Serial) {
+        let windows: Vec<Window> = self
+            .space
+            .elements()
+            .filter(|w| accepts_focus(w))
+            .cloned()
+            .collect();
+        let [.., next, top] = windows.as_slice() else {
+            return;
+        };
+        self.lower_window(top);
+        self.focus_window(next, serial);
+    }
+
+    /// Puts `window` below every other window
+    pub fn lower_window(&mut self, window: &Window) {
+        let others: Vec<Window> = self
+            .space
+            .elements()
+            .filter(|w| *w != window)
+            .cloned()
+            .collect();
+        // The space cannot lower windows, everything else is raised above it instead
+        for other in &others {
+            self.space.raise_element(other, false);
+        }
+        self.queue_redraw_all();
+    }
+
+    /// Moves or resizes `window` with the pointer, until `button` is released.
+    ///
+    /// Resizing moves the edges nearest to the pointer, see [`ResizeEdge::from_position`].
+    fn grab_window(&mut self, window: Window, button: u32, resize: bool) {
+        // Windows moved or resized by hand leave the layout
+        self.float_window(&window);
+        let pointer = self.pointer_handle.current_location();
+        self.untile_from_edge(&window, (!resize).then_some(pointer));
+        let Some(geometry) = self.space.element_geometry(&window) else {
+            return;
+        };
+        let Some(loc) = self.space.element_location(&window) else {
+            return;
+        };
+
+        let pointer = self.pointer_handle.clone();
+        let location = pointer.current_location();
+        let start_data = GrabStartData {
+            focus: window
+                .wl_surface()
+                .map(|surf| (surf.into_owned(), loc.to_f64())),
+            button,
+            location,
+        };
+        let serial = SERIAL_COUNTER.next_serial();
+        if resize {
+            let edges = ResizeEdge::from_position(geometry.size, location - geometry.loc.to_f64());
+            // Like for client requests, the rectangle is the location of the window in the space,
+            // which `resize_grab::handle_commit` adjusts when resizing from the top or left
+            let initial_rect = Rectangle::from_loc_and_size(loc, geometry.size);
+            let grab = ResizePointerGrab::start(start_data, window, initial_rect, edges);
+            pointer.set_grab(self, grab, serial, Focus::Clear);
+        } else {
+            let grab = MovePointerGrab {
+                start_data,
+                window,
+                initial_window_location: loc,
+            };
+            pointer.set_grab(self, grab, serial, Focus::Clear);
+        }
+    }
+
+    /// Moves or resizes `window` with the keyboard, see [`MoveResizeKeyboardGrab`]
+    fn grab_window_with_keys(&mut self, window: Window, resize: bool, serial: Serial) {
+        let keyboard = self.keyboard_handle.clone();
+        if keyboard.is_grabbed() {
+            return;
+        }
+        // Windows moved or resized by hand leave the layout
+        self.float_window(&window);
+        // The pointer may be anywhere, the window keeps its top-left corner instead
+        let corner = self
+            .space
+            .element_geometry(&window)
+            .map(|geo| geo.loc.to_f64());
+        self.untile_from_edge(&window, corner.filter(|_| !resize));
+        let Some(loc) = self.space.element_location(&window) else {
+            return;
+        };
+
+        let start_data = KeyboardGrabStartData {
+            focus: keyboard.current_focus(),
+        };
+        let initial_rect = Rectangle::from_loc_and_size(loc, window.geometry().size);
+        let modifiers = keyboard.modifier_state();
+        let grab =
+            MoveResizeKeyboardGrab::start(start_data, window, initial_rect, resize, modifiers);
+        keyboard.set_grab(self, grab, serial);
+    }
+}
+
+/// Asks `window` to close
+pub fn close_window(window: &Window) {
+    if let Some(toplevel) = window.toplevel() {
+        toplevel.send_close();
+    } else if let Some(surface) = window.x11_surface() {
+        if let Err(err) = surface.close() {
+            error!(?err, "Unable to close X11 window");
+        }
+    }
+}
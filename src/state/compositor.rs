@@ -2,18 +2,20 @@ use std::{borrow::BorrowMut, cell::RefCell};
 
 use smithay::{
     backend::renderer::utils::on_commit_buffer_handler,
-    delegate_compositor, delegate_shm,
+    delegate_compositor, delegate_fractional_scale, delegate_shm, delegate_viewporter,
     desktop::PopupKind,
     reexports::wayland_server::{
         protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface},
         Client,
     },
+    utils::SERIAL_COUNTER,
     wayland::{
         buffer::BufferHandler,
         compositor::{
             get_parent, is_sync_subsurface, with_states, CompositorClientState, CompositorHandler,
             CompositorState,
         },
+        fractional_scale::FractionalScaleHandler,
         shell::xdg::XdgPopupSurfaceData,
         shm::{ShmHandler, ShmState},
     },
@@ -22,6 +24,7 @@ use smithay::{
 use tracing::error;
 
 use super::{
+    send_surface_output_state,
     xdg_shell::{self, resize_grab},
     ClientState, ThingState,
 };
@@ -51,12 +54,36 @@ impl CompositorHandler for ThingState {
             while let Some(parent) = get_parent(&root) {
                 root = parent;
             }
-            if let Some(window) = self.space.elements().find(|w| {
-                w.toplevel()
-                    .map(|t| t.wl_surface() == &root)
-                    .unwrap_or(false)
-            }) {
-                window.on_commit();
+            match self
+                .space
+                .elements()
+                .find(|w| {
+                    w.toplevel()
+                        .map(|t| t.wl_surface() == &root)
+                        .unwrap_or(false)
+                })
+                .cloned()
+            {
+                Some(window) => {
+                    window.on_commit();
+                    // New windows are focused once they are shown
+                    if self.place_if_sized(&window) {
+                        self.focus_window(&window, SERIAL_COUNTER.next_serial());
+                    }
+                    self.queue_redraw_for_window(&window);
+                }
+                // Popups, X11 windows, or surfaces that are not displayed yet
+                None => {
+                    // Windows on other workspaces or minimized still keep their state up to date
+                    let hidden = self
+                        .hidden_windows()
+                        .into_iter()
+                        .find(|w| w.toplevel().is_some_and(|t| t.wl_surface() == &root));
+                    match hidden {
+                        Some(window) => window.on_commit(),
+                        None => self.queue_redraw_all(),
+                    }
+                }
             }
         };
 
@@ -107,3 +134,29 @@ impl ShmHandler for ThingState {
 }
 
 delegate_shm!(ThingState);
+
+// FRACTIONAL SCALE
+
+impl FractionalScaleHandler for ThingState {
+    fn new_fractional_scale(&mut self, surface: WlSurface) {
+        let mut root = surface.clone();
+        while let Some(parent) = get_parent(&root) {
+            root = parent;
+        }
+
+        // Use the main output of the window if it is already mapped, the first output otherwise
+        let output = self
+            .window_for_surface(root)
+            .and_then(|window| self.space.outputs_for_element(window).into_iter().next())
+            .or_else(|| self.space.outputs().next().cloned());
+        if let Some(output) = output {
+            with_states(&surface, |states| {
+                send_surface_output_state(&surface, states, &output)
+            });
+        }
+    }
+}
+
+delegate_fractional_scale!(ThingState);
+
+delegate_viewporter!(ThingState);
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use smithay::{output::Output, reexports::calloop::ping::make_ping};
+use tracing::{error, info};
+
+use crate::{
+    backend::output_scale,
+    config::{Config, OutputConfig},
+};
+
+use super::ThingState;
+
+impl ThingState {
+    /// Settings of the output named `name`, the command line options taking precedence over the
+    /// configuration file
+    pub fn output_config(&self, name: &str) -> OutputConfig {
+        self.output_overrides.clone().or(self.config.output(name))
+    }
+
+    /// Watches the configuration file, and reloads it whenever it changes
+    pub fn watch_config(&mut self) {
+        let Some(path) = self.config_path.clone() else {
+            return;
+        };
+        // Editors often replace the file rather than writing to it, so its directory is watched
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => Path::new(".").to_owned(),
+        };
+        let file_name = path.file_name().map(ToOwned::to_owned);
+
+        // The changes are noticed on the thread of the watcher, several of them waking the event
+        // loop only once
+        let (ping, ping_source) = match make_ping() {
+            Ok(ping) => ping,
+            Err(err) => return error!(?err, "Unable to watch the configuration file"),
+        };
+        let watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let event = match res {
+                Ok(event) => event,
+                Err(err) => return error!(?err, "Error when watching the configuration file"),
+            };
+            let changed = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == file_name.as_deref());
+            if changed {
+                ping.ping();
+            }
+        });
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(err) => return error!(?err, "Unable to watch the configuration file"),
+        };
+        if let Err(err) = watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            return error!(?err, "Unable to watch {}", dir.display());
+        }
+
+        let res = self
+            .loop_handle
+            .insert_source(ping_source, move |_, _, data| {
+                // The file may have been removed, only reload when it exists
+                if path.exists() {
+                    data.state.reload_config();
+                }
+            });
+        if let Err(err) = res {
+            return error!(?err, "Unable to watch the configuration file");
+        }
+        self.config_watcher = Some(watcher);
+    }
+
+    /// Reloads the configuration file, keeping the current configuration if it is invalid
+    pub fn reload_config(&mut self) {
+        let Some(path) = self.config_path.as_deref() else {
+            return;
+        };
+        let config = match Config::load(path) {
+            Ok(config) => config,
+            Err(err) => return error!("{}", err),
+        };
+        info!("Configuration reloaded from {}", path.display());
+        self.apply_config(config);
+    }
+
+    /// Replaces the current configuration, and applies what changed
+    pub fn apply_config(&mut self, config: Config) {
+        let old = std::mem::replace(&mut self.config, config);
+
+        if old.keyboard != self.config.keyboard {
+            self.keyboard_handle.change_repeat_info(
+                self.config.keyboard.repeat_rate,
+                self.config.keyboard.repeat_delay,
+            );
+        }
+        if !old.keyboard.same_keymap(&self.config.keyboard) {
+            // Sends the new keymap to every client
+            let keyboard = self.keyboard_handle.clone();
+            let keyboard_config = self.config.keyboard.clone();
+            if let Err(err) = keyboard.set_xkb_config(self, keyboard_config.xkb_config()) {
+                error!(?err, "Invalid keyboard layout, keeping the current one");
+            }
+        }
+
+        let outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        for output in &outputs {
+            self.configure_output(output);
+        }
+        self.arrange_outputs();
+
+        if old.rules != self.config.rules {
+            self.reapply_window_rules();
+        }
+        if old.workspaces != self.config.workspaces {
+            self.apply_workspace_count();
+        }
+        if old.tiling.layout != self.config.tiling.layout {
+            self.set_layout(self.config.tiling.layout);
+        } else if old.tiling != self.config.tiling {
+            self.retile_all();
+        }
+
+        self.queue_redraw_all();
+    }
+
+    /// Applies the configured scale and transform to `output`.
+    ///
+    /// Modes are only applied when the outputs are created, and settings which are not set
+    /// are left to the backend.
+    fn configure_output(&mut self, output: &Output) {
+        let config = self.output_config(&output.name());
+
+        let scale = config
+            .scale
+            .map(output_scale)
+            .filter(|scale| *scale != output.current_scale());
+        let transform = config
+            .transform
+            .filter(|transform| *transform != output.current_transform());
+        if scale.is_some() || transform.is_some() {
+            output.change_current_state(None, transform, scale, None);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use smithay::{
+    desktop::{find_popup_root_surface, Window},
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{IsAlive, SERIAL_COUNTER},
+};
+
+use super::{window::send_configure, ThingState};
+
+impl ThingState {
+    /// Records the activation of the window of `focused`, and activates only that window.
+    ///
+    /// Called whenever the keyboard focus changes.
+    pub fn on_focus_changed(&mut self, focused: Option<&WlSurface>) {
+        let focused = focused.and_then(|surface| self.surface_window(surface));
+
+        if let Some(window) = &focused {
+            self.focus_stack.retain(|w| w != window);
+            self.focus_stack.push(window.clone());
+        }
+
+        for window in self.space.elements() {
+            let changed = window.set_activated(Some(window) == focused.as_ref());
+            if changed {
+                if let Some(toplevel) = window.toplevel() {
+                    send_configure(toplevel);
+                }
+            }
+        }
+        self.queue_redraw_all();
+    }
+
+    /// Removes `window` from the space.
+    ///
+    /// If it was focused, the focus goes to the window focused before it on the same output.
+    pub fn unmap_window(&mut self, window: &Window) {
+        let output = self.space.outputs_for_element(window).into_iter().next();
+        // The surface of a destroyed window may already be gone, leaving nothing focused
+        let focused = self.keyboard_handle.current_focus().is_some_and(|focus| {
+            !focus.alive() || self.surface_window(&focus).as_ref() == Some(window)
+        });
+
+        self.queue_redraw_for_window(window);
+        self.space.unmap_elem(window);
+        if window.alive() && window.set_activated(false) {
+            if let Some(toplevel) = window.toplevel() {
+                send_configure(toplevel);
+            }
+        }
+        if let Some(output) = &output {
+            self.retile(output);
+        }
+        if focused {
+            self.focus_fallback(output.as_ref());
+        }
+    }
+
+    /// Forgets about `window`, once it has been destroyed
+    pub fn remove_window(&mut self, window: &Window) {
+        self.unmap_window(window);
+        self.focus_stack.retain(|w| w != window);
+        self.tiling_order.retain(|w| w != window);
+        self.unplaced_windows.retain(|w| w != window);
+        self.minimized_windows.retain(|(w, _)| w != window);
+        self.remove_hidden_window(window);
+        self.workspaces_changed();
+    }
+
+    /// The window `surface` belongs to, popups belonging to the window they were opened from
+    fn surface_window(&self, surface: &WlSurface) -> Option<Window> {
+        let root = match self.popup_manager.find_popup(surface) {
+            Some(popup) => find_popup_root_surface(&popup).ok()?,
+            None => surface.clone(),
+        };
+        self.window_for_surface(root).cloned()
+    }
+
+    /// Focuses the window focused last on `output`, or on any output if `None`, and nothing if
+    /// there is none
+    pub fn focus_fallback(&mut self, output: Option<&Output>) {
+        let window = self
+            .focus_stack
+            .iter()
+            .rev()
+            .find(|w| {
+                let outputs = self.space.outputs_for_element(w);
+                match output {
+                    Some(output) => outputs.contains(output),
+                    None => !outputs.is_empty(),
+                }
+            })
+            .cloned();
+
+        let serial = SERIAL_COUNTER.next_serial();
+        match window {
+            Some(window) => self.focus_window(&window, serial),
+            None => {
+                let keyboard = self.keyboard_handle.clone();
+                keyboard.set_focus(self, None, serial);
+            }
+        }
+    }
+}
@@ -1,17 +1,21 @@
 use smithay::{
-    backend::input::{Axis, AxisSource, ButtonState, Event, InputBackend, InputEvent, MouseButton},
+    backend::input::{Axis, AxisSource, ButtonState, Event, InputBackend, InputEvent, KeyState},
+    desktop::Window,
     input::{
-        keyboard::FilterResult,
-        pointer::{AxisFrame, ButtonEvent, Focus, GrabStartData, MotionEvent},
+        keyboard::{FilterResult, Keysym},
+        pointer::{AxisFrame, ButtonEvent, MotionEvent},
     },
-    utils::{Logical, Point, Serial, SERIAL_COUNTER},
+    utils::{Logical, Physical, Point, Rectangle, Serial, Size, Transform, SERIAL_COUNTER},
     wayland::seat::WaylandFocus,
 };
 use tracing::trace;
 
-use crate::state::xdg_shell::{move_grab::MovePointerGrab, resize_grab::{ResizeEdge, ResizePointerGrab}};
+use crate::config::{
+    bindings::{Action, Modifiers},
+    FocusPolicy,
+};
 
-use super::ThingState;
+use super::{rules::accepts_focus, ThingState};
 
 impl ThingState {
     pub fn process_input_event<I: InputBackend>(&mut self, event: InputEvent<I>) {
@@ -42,15 +46,51 @@ impl ThingState {
 
         let serial = SERIAL_COUNTER.next_serial();
         let kbh = self.keyboard_handle.clone();
-        kbh.input::<(), _>(
+        let action = kbh.input(
             self,
             event.key_code(),
             event.state(),
             serial,
             event.time_msec(),
-            //TODO: Have magic keybinds to force quit the compositor
-            |_, _, _| FilterResult::Forward,
+            |state, modifiers, handle| {
+                let keycode = handle.raw_code();
+                if event.state() == KeyState::Released {
+                    // Clients never saw the press of keys which triggered a binding
+                    if let Some(index) = state.suppressed_keys.iter().position(|k| *k == keycode) {
+                        state.suppressed_keys.swap_remove(index);
+                        return FilterResult::Intercept(None);
+                    }
+                    return FilterResult::Forward;
+                }
+
+                // Hard-wired, so the compositor can always be stopped
+                let exit = modifiers.ctrl
+                    && modifiers.alt
+                    && handle.raw_syms().contains(&Keysym::BackSpace);
+                if exit {
+                    state.suppressed_keys.push(keycode);
+                    return FilterResult::Intercept(Some(Action::Quit));
+                }
+
+                let action = state
+                    .config
+                    .keybindings
+                    .action(Modifiers::from(modifiers), handle.raw_syms())
+                    .cloned();
+                match action {
+                    Some(action) => {
+                        trace!(?action, "Key binding triggered");
+                        state.suppressed_keys.push(keycode);
+                        FilterResult::Intercept(Some(action))
+                    }
+                    None => FilterResult::Forward,
+                }
+            },
         );
+
+        if let Some(Some(action)) = action {
+            self.run_action(action, serial);
+        }
     }
 
     fn process_pointer_motion<I: InputBackend>(
@@ -73,6 +113,9 @@ impl ThingState {
             },
         );
         pointer.frame(self);
+        self.focus_follow_pointer(location);
+        // The pointer may have moved a window, or changed what is under it
+        self.queue_redraw_all();
     }
 
     fn process_pointer_motion_absolute<I: InputBackend>(
@@ -81,9 +124,26 @@ impl ThingState {
     ) {
         use smithay::backend::input::AbsolutePositionEvent;
 
-        let output = self.space.outputs().next().unwrap();
-        let output_geo = self.space.output_geometry(output).unwrap();
-        let location = event.position_transformed(output_geo.size) + output_geo.loc.to_f64();
+        let columns: Vec<Column> = self
+            .space
+            .outputs()
+            .filter_map(|output| {
+                Some(Column {
+                    mode_size: output.current_mode()?.size,
+                    scale: output.current_scale().fractional_scale(),
+                    transform: output.current_transform(),
+                    geometry: self.space.output_geometry(output)?,
+                })
+            })
+            .collect();
+        let window_size = columns.iter().fold((0, 0), |(w, h), column| {
+            (w + column.mode_size.w, h.max(column.mode_size.h))
+        });
+        // In physical pixels of the window, whatever the type says
+        let position = event.position_transformed(window_size.into());
+        let Some(location) = window_location(&columns, (position.x, position.y).into()) else {
+            return;
+        };
 
         let element_under = self.surface_under(location);
 
@@ -98,6 +158,8 @@ impl ThingState {
             },
         );
         pointer.frame(self);
+        self.focus_follow_pointer(location);
+        self.queue_redraw_all();
     }
 
     fn process_pointer_button<I: InputBackend>(
@@ -111,72 +173,35 @@ impl ThingState {
         let state = event.state();
         let serial = SERIAL_COUNTER.next_serial();
 
-        // =====
-        // TEMPORARY LOGIC
-        //
-        // This will be moved and made configurable in the future.
-        // For now, it's just to have a minimal way to changing focus, moving windows, etc.
-        // =====
-
         if state == ButtonState::Pressed {
-            if let Some((window, loc)) = self
+            self.focus_on_click(pointer.current_location(), serial);
+
+            let modifiers = Modifiers::from(&self.keyboard_handle.modifier_state());
+            let window = self
                 .space
                 .element_under(pointer.current_location())
-                .map(|(w, p)| (w.clone(), p))
-            {
-                match event.button() {
-                    // MOVE GRAB
-                    Some(MouseButton::Left) => {
-                        self.update_keyboard_focus_for_cursor_position(
-                            pointer.current_location(),
-                            serial,
-                        );
-
-                        let kb = self.keyboard_handle.clone();
-                        if kb.modifier_state().alt {
-                            let focus = window
-                                .wl_surface()
-                                .map(|surf| (surf.into_owned(), loc.to_f64()));
-                            let start_data = GrabStartData {
-                                focus,
-                                button: event.button_code(),
-                                location: pointer.current_location(),
-                            };
-                            let grab = MovePointerGrab {
-                                start_data,
-                                window,
-                                initial_window_location: loc,
-                            };
-                            pointer.set_grab(self, grab, serial, Focus::Clear);
-                        }
-                    }
-
-                    // RESIZE GRAB
-                    Some(MouseButton::Right) => {
-                        let kb = self.keyboard_handle.clone();
-                        if kb.modifier_state().alt {
-                            let focus = window
-                                .wl_surface()
-                                .map(|surf| (surf.into_owned(), loc.to_f64()));
-                            let start_data = GrabStartData {
-                                focus,
-                                button: event.button_code(),
-                                location: pointer.current_location(),
-                            };
-                            let initial_rect = self.space.element_geometry(&window).unwrap();
-                            let grab = ResizePointerGrab::start(
-                                start_data,
-                                window,
-                                initial_rect,
-                                ResizeEdge::BOTTOM_RIGHT,
-                            );
-                            pointer.set_grab(self, grab, serial, Focus::Clear);
-                        }
-                    }
+                .map(|(w, _)| w.clone());
+            // Clients get the press when there is no window for the action to apply to
+            let action = self
+                .config
+                .mousebindings
+                .action(modifiers, button)
+                .filter(|action| window.is_some() || !action.needs_window())
+                .cloned();
+            if let Some(action) = action {
+                self.run_pointer_action(action, window, button, serial);
 
-                    _ => {}
+                // Grabs hide the button from clients, and need to know it is pressed
+                if !pointer.is_grabbed() {
+                    self.suppressed_buttons.push(button);
+                    self.queue_redraw_all();
+                    return;
                 }
             }
+        } else if let Some(index) = self.suppressed_buttons.iter().position(|b| *b == button) {
+            // Clients never saw the press of buttons which triggered a binding
+            self.suppressed_buttons.swap_remove(index);
+            return;
         }
 
         pointer.button(
@@ -189,6 +214,7 @@ impl ThingState {
             },
         );
         pointer.frame(self);
+        self.queue_redraw_all();
     }
 
     fn process_pointer_axis<I: InputBackend>(
@@ -245,20 +271,142 @@ impl ThingState {
         pointer.frame(self);
     }
 
-    fn update_keyboard_focus_for_cursor_position(
-        &mut self,
-        location: Point<f64, Logical>,
-        serial: Serial,
-    ) {
-        let keyboard = self.keyboard_handle.clone();
+    /// Applies the focus policy when a button is pressed with the pointer at `location`
+    fn focus_on_click(&mut self, location: Point<f64, Logical>, serial: Serial) {
+        let Some(window) = self.space.element_under(location).map(|(w, _)| w.clone()) else {
+            return;
+        };
 
-        if let Some((window, _)) = self
-            .space
-            .element_under(location)
-            .map(|(w, p)| (w.clone(), p))
+        // Whatever the policy, as the pointer may not have moved since the focus went elsewhere
+        let focus = &self.config.focus;
+        let raise = focus.raise_on_click || focus.raise_on_focus;
+        if self.focused_window().as_ref() != Some(&window) {
+            self.set_pointer_focus(Some(&window), raise, serial);
+        } else if focus.raise_on_click {
+            self.space.raise_element(&window, false);
+            self.queue_redraw_all();
+        }
+    }
+
+    /// Applies the focus policy when the pointer moves to `location`
+    fn focus_follow_pointer(&mut self, location: Point<f64, Logical>) {
+        let policy = self.config.focus.policy;
+        // Grabs, like moving a window or popup menus, decide where the focus goes meanwhile
+        if policy == FocusPolicy::Click
+            || self.pointer_handle.is_grabbed()
+            || self.keyboard_handle.is_grabbed()
         {
-            self.space.raise_element(&window, true);
-            keyboard.set_focus(self, window.wl_surface().map(|s| s.into_owned()), serial);
+            return;
+        }
+
+        let window = self.space.element_under(location).map(|(w, _)| w.clone());
+        if window.is_none() && policy == FocusPolicy::Sloppy {
+            return;
+        }
+        if self.focused_window() != window {
+            let raise = self.config.focus.raise_on_focus;
+            self.set_pointer_focus(window.as_ref(), raise, SERIAL_COUNTER.next_serial());
         }
     }
+
+    /// Gives the keyboard focus to `window`, or to nothing if `None`, raising it if `raise` is set
+    fn set_pointer_focus(&mut self, window: Option<&Window>, raise: bool, serial: Serial) {
+        if let Some(window) = window.filter(|_| raise) {
+            self.space.raise_element(window, false);
+            self.queue_redraw_all();
+        }
+        if window.is_some_and(|w| !accepts_focus(w)) {
+            return;
+        }
+        let keyboard = self.keyboard_handle.clone();
+        keyboard.set_focus(
+            self,
+            window.and_then(|w| w.wl_surface()).map(|s| s.into_owned()),
+            serial,
+        );
+    }
+}
+
+/// An output as displayed by a nested backend, in a column of its window
+#[derive(Debug, Clone, Copy)]
+struct Column {
+    /// Size of the column, which is the size of the output before its transform
+    mode_size: Size<i32, Physical>,
+    scale: f64,
+    transform: Transform,
+    /// Geometry of the output in the space
+    geometry: Rectangle<i32, Logical>,
+}
+
+/// Location in the space of `position`, in a window showing `columns` side by side from left to
+/// right
+fn window_location(
+    columns: &[Column],
+    position: Point<f64, Physical>,
+) -> Option<Point<f64, Logical>> {
+    let mut x = position.x;
+    for (i, column) in columns.iter().enumerate() {
+        // The last column also takes what is past the window
+        if x >= column.mode_size.w as f64 && i + 1 < columns.len() {
+            x -= column.mode_size.w as f64;
+            continue;
+        }
+        let untransformed_size = column.transform.transform_size(column.geometry.size);
+        let position = Point::<f64, Physical>::from((x, position.y)).to_logical(column.scale);
+        let location = column
+            .transform
+            .invert()
+            .transform_point_in(position, &untransformed_size.to_f64());
+        return Some(location + column.geometry.loc.to_f64());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(
+        mode_size: (i32, i32),
+        scale: f64,
+        transform: Transform,
+        geometry: (i32, i32, i32, i32),
+    ) -> Column {
+        Column {
+            mode_size: mode_size.into(),
+            scale,
+            transform,
+            geometry: Rectangle::from_loc_and_size(
+                (geometry.0, geometry.1),
+                (geometry.2, geometry.3),
+            ),
+        }
+    }
+
+    #[test]
+    fn columns_are_scaled_separately() {
+        let columns = [
+            column((800, 600), 1.0, Transform::Normal, (0, 0, 800, 600)),
+            column((2000, 1000), 2.0, Transform::Normal, (800, 0, 1000, 500)),
+        ];
+        let location = |x: f64, y: f64| window_location(&columns, (x, y).into()).unwrap();
+        assert_eq!(location(400., 300.), (400., 300.).into());
+        assert_eq!(location(900., 100.), (850., 50.).into());
+        assert_eq!(location(2800., 1000.), (1800., 500.).into());
+        assert_eq!(window_location(&[], (0., 0.).into()), None);
+    }
+
+    #[test]
+    fn rotated_columns_map_corners_to_corners() {
+        let columns = [column((800, 600), 1.0, Transform::_90, (0, 0, 600, 800))];
+        let mut corners: Vec<(i32, i32)> = [(0., 0.), (800., 0.), (0., 600.), (800., 600.)]
+            .into_iter()
+            .map(|position| {
+                let location = window_location(&columns, position.into()).unwrap();
+                (location.x.round() as i32, location.y.round() as i32)
+            })
+            .collect();
+        corners.sort();
+        assert_eq!(corners, [(0, 0), (0, 800), (600, 0), (600, 800)]);
+    }
 }
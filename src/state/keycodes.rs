@@ -0,0 +1,10 @@
+// Raw XKB keycodes of the keys handled by keyboard grabs, which do not depend on the keyboard
+// layout
+
+pub const KEY_ESCAPE: u32 = 9;
+pub const KEY_ENTER: u32 = 36;
+pub const KEY_KP_ENTER: u32 = 104;
+pub const KEY_UP: u32 = 111;
+pub const KEY_LEFT: u32 = 113;
+pub const KEY_RIGHT: u32 = 114;
+pub const KEY_DOWN: u32 = 116;
@@ -0,0 +1,232 @@
+use smithay::{
+    desktop::Window,
+    output::Output,
+    utils::{Logical, Point, Rectangle, Size},
+};
+use tracing::error;
+
+use crate::config::Placement;
+
+use super::{window::window_state, ThingState};
+
+/// Offset between a cascaded window and the one below it
+const CASCADE_STEP: i32 = 32;
+
+impl ThingState {
+    /// Places `window` if it was waiting for its size to be known, and it now is.
+    ///
+    /// Returns whether it got placed.
+    pub fn place_if_sized(&mut self, window: &Window) -> bool {
+        if window.geometry().size.is_empty() {
+            return false;
+        }
+        let Some(index) = self.unplaced_windows.iter().position(|w| w == window) else {
+            return false;
+        };
+        self.unplaced_windows.swap_remove(index);
+        self.place_window(window);
+        true
+    }
+
+    /// Moves `window` to where new windows go: centred over their parent for dialogs, or according
+    /// to the configured [`Placement`] on the output under the pointer, unless the window rules
+    /// say otherwise.
+    ///
+    /// The size of the window must be known.
+    pub fn place_window(&mut self, window: &Window) {
+        let (rules, maximized, fullscreen) = {
+            let state = window_state(window);
+            (state.rules.clone(), state.maximized, state.fullscreen)
+        };
+        // Wayland windows were already asked for that size, see `ThingState::apply_window_rules`
+        let size = match (rules.size, window.x11_surface()) {
+            (Some(size), Some(_)) => size,
+            _ => window.geometry().size,
+        };
+
+        let parent = self
+            .parent_window(window)
+            .and_then(|parent| self.space.element_geometry(&parent));
+        // Dialogs are not tiled
+        let floating = rules.floating.unwrap_or(parent.is_some());
+        window_state(window).floating = floating;
+        self.tiling_order.push(window.clone());
+
+        let output = rules
+            .output
+            .as_deref()
+            .and_then(|name| self.space.outputs().find(|o| o.name() == name).cloned())
+            .or_else(|| self.pointer_output());
+        let area = output
+            .as_ref()
+            .and_then(|output| self.space.output_geometry(output));
+
+        let loc = match (rules.position, parent, area) {
+            (Some(position), _, Some(area)) => area.loc + position,
+            (_, Some(parent), _) if rules.output.is_none() => {
+                let center = parent.loc + Point::from((parent.size.w / 2, parent.size.h / 2));
+                let output = self.space.output_under(center.to_f64()).next().cloned();
+                let loc = center - Point::from((size.w / 2, size.h / 2));
+                match output.and_then(|output| self.space.output_geometry(&output)) {
+                    Some(area) => clamp(loc, area, size),
+                    None => loc,
+                }
+            }
+            (_, _, Some(area)) => match self.config.placement {
+                Placement::Center => center(area, size),
+                Placement::Cascade => self.cascade(window, area, size),
+                Placement::Smart => self.smart(window, area, size),
+            },
+            (_, _, None) => return,
+        };
+
+        self.space.map_element(window.clone(), loc, false);
+        if let Some(surface) = window.x11_surface() {
+            if let Err(err) = surface.configure(Rectangle::from_loc_and_size(loc, size)) {
+                error!(?err, "Unable to configure X11 window");
+            }
+        }
+        self.queue_redraw_for_window(window);
+
+        // Windows asked to be maximized or fullscreen before their first buffer get the area of
+        // the output they were placed on, and the placed geometry once they leave that state
+        if maximized || fullscreen {
+            window_state(window).restore_geometry = Some(Rectangle::from_loc_and_size(loc, size));
+        }
+        if rules.fullscreen || fullscreen {
+            self.fullscreen_window(window, output);
+        } else if maximized {
+            self.maximize_window(window);
+        } else {
+            self.retile_window_output(window);
+        }
+        if let Some(workspace) = rules.workspace {
+            self.move_to_workspace(window, workspace);
+        }
+        self.workspaces_changed();
+    }
+
+    /// The window `window` is a dialog of, if any
+    fn parent_window(&self, window: &Window) -> Option<Window> {
+        if let Some(toplevel) = window.toplevel() {
+            let parent = toplevel.parent()?;
+            return self.window_for_surface(parent).cloned();
+        }
+
+        let parent = window.x11_surface()?.is_transient_for()?;
+        self.space
+            .elements()
+            .find(|w| w.x11_surface().is_some_and(|s| s.window_id() == parent))
+            .cloned()
+    }
+
+    /// The output under the pointer, or the first one, where new windows are placed
+    pub fn pointer_output(&self) -> Option<Output> {
+        let location = self.pointer_handle.current_location();
+        self.space
+            .output_under(location)
+            .next()
+            .or_else(|| self.space.outputs().next())
+            .cloned()
+    }
+
+    /// Geometries of the windows other than `window` visible in `area`, from bottom to top
+    fn windows_in(
+        &self,
+        window: &Window,
+        area: Rectangle<i32, Logical>,
+    ) -> Vec<Rectangle<i32, Logical>> {
+        self.space
+            .elements()
+            .filter(|w| *w != window)
+            .filter_map(|w| self.space.element_geometry(w))
+            .filter(|geo| geo.overlaps(area))
+            .collect()
+    }
+
+    /// Location below and to the right of the top-most window, starting over from the corner of
+    /// `area` when it does not fit anymore
+    fn cascade(
+        &self,
+        window: &Window,
+        area: Rectangle<i32, Logical>,
+        size: Size<i32, Logical>,
+    ) -> Point<i32, Logical> {
+        let Some(top) = self.windows_in(window, area).pop() else {
+            return area.loc;
+        };
+
+        let loc = top.loc + Point::from((CASCADE_STEP, CASCADE_STEP));
+        let fits = loc.x + size.w <= area.loc.x + area.size.w
+            && loc.y + size.h <= area.loc.y + area.size.h;
+        if fits {
+            loc
+        } else {
+            area.loc
+        }
+    }
+
+    /// Location where the window overlaps the other windows the least, preferring the top then the
+    /// left of `area`.
+    ///
+    /// The candidates are the sides of `area` and of the other windows, which includes every
+    /// location where the window does not overlap anything if there is one.
+    fn smart(
+        &self,
+        window: &Window,
+        area: Rectangle<i32, Logical>,
+        size: Size<i32, Logical>,
+    ) -> Point<i32, Logical> {
+        let others = self.windows_in(window, area);
+
+        let xs: Vec<i32> = [area.loc.x, area.loc.x + area.size.w - size.w]
+            .into_iter()
+            .chain(
+                others
+                    .iter()
+                    .flat_map(|geo| [geo.loc.x + geo.size.w, geo.loc.x - size.w]),
+            )
+            .collect();
+        let ys: Vec<i32> = [area.loc.y, area.loc.y + area.size.h - size.h]
+            .into_iter()
+            .chain(
+                others
+                    .iter()
+                    .flat_map(|geo| [geo.loc.y + geo.size.h, geo.loc.y - size.h]),
+            )
+            .collect();
+
+        let overlap = |loc: Point<i32, Logical>| -> i64 {
+            let rect = Rectangle::from_loc_and_size(loc, size);
+            others
+                .iter()
+                .filter_map(|geo| rect.intersection(*geo))
+                .map(|inter| inter.size.w as i64 * inter.size.h as i64)
+                .sum()
+        };
+
+        ys.iter()
+            .flat_map(|&y| xs.iter().map(move |&x| Point::from((x, y))))
+            .map(|loc| clamp(loc, area, size))
+            .min_by_key(|&loc| (overlap(loc), loc.y, loc.x))
+            .unwrap_or(area.loc)
+    }
+}
+
+/// Location centring a window of size `size` in `area`
+fn center(area: Rectangle<i32, Logical>, size: Size<i32, Logical>) -> Point<i32, Logical> {
+    let loc = area.loc + Point::from(((area.size.w - size.w) / 2, (area.size.h - size.h) / 2));
+    clamp(loc, area, size)
+}
+
+/// Moves `loc` so that a window of size `size` there is inside `area`, keeping its top-left
+/// corner inside when it is too big
+fn clamp(
+    loc: Point<i32, Logical>,
+    area: Rectangle<i32, Logical>,
+    size: Size<i32, Logical>,
+) -> Point<i32, Logical> {
+    let x = loc.x.min(area.loc.x + area.size.w - size.w).max(area.loc.x);
+    let y = loc.y.min(area.loc.y + area.size.h - size.h).max(area.loc.y);
+    (x, y).into()
+}
@@ -0,0 +1,213 @@
+use smithay::{
+    desktop::Window,
+    utils::{Logical, Point, Size},
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+
+use crate::config::WindowRule;
+
+use super::{
+    window::{send_configure, window_state},
+    ThingState,
+};
+
+/// Settings of a window given by the rules matching it, unset when no rule sets them
+#[derive(Debug, Clone, Default)]
+pub struct RuleSettings {
+    pub workspace: Option<usize>,
+    pub output: Option<String>,
+    pub floating: Option<bool>,
+    pub size: Option<Size<i32, Logical>>,
+    pub position: Option<Point<i32, Logical>>,
+    pub fullscreen: bool,
+    pub opacity: Option<f32>,
+    pub never_focus: bool,
+}
+
+impl RuleSettings {
+    /// Takes the settings set by `rule`
+    fn apply(&mut self, rule: &WindowRule) {
+        if let Some(workspace) = rule.workspace {
+            self.workspace = Some(workspace);
+        }
+        if let Some(output) = &rule.output {
+            self.output = Some(output.clone());
+        }
+        if let Some(floating) = rule.floating {
+            self.floating = Some(floating);
+        }
+        if let Some(size) = rule.size {
+            self.size = Some(size.into());
+        }
+        if let Some(position) = rule.position {
+            self.position = Some(position.into());
+        }
+        if let Some(fullscreen) = rule.fullscreen {
+            self.fullscreen = fullscreen;
+        }
+        if let Some(opacity) = rule.opacity {
+            self.opacity = Some(opacity);
+        }
+        if let Some(never_focus) = rule.never_focus {
+            self.never_focus = never_focus;
+        }
+    }
+}
+
+/// Properties of a window the rules match on
+#[derive(Debug, Default)]
+struct WindowProperties {
+    app_id: Option<String>,
+    title: Option<String>,
+    class: Option<String>,
+    instance: Option<String>,
+}
+
+impl WindowProperties {
+    fn of(window: &Window) -> Self {
+        if let Some(toplevel) = window.toplevel() {
+            with_states(toplevel.wl_surface(), |states| {
+                let data = states.data_map.get::<XdgToplevelSurfaceData>()?;
+                let data = data.lock().ok()?;
+                Some(WindowProperties {
+                    app_id: data.app_id.clone(),
+                    title: data.title.clone(),
+                    ..Default::default()
+                })
+            })
+            .unwrap_or_default()
+        } else if let Some(surface) = window.x11_surface() {
+            WindowProperties {
+                app_id: None,
+                title: Some(surface.title()),
+                class: Some(surface.class()),
+                instance: Some(surface.instance()),
+            }
+        } else {
+            WindowProperties::default()
+        }
+    }
+
+    /// Whether every pattern of `rule` matches
+    fn matches(&self, rule: &WindowRule) -> bool {
+        let matches = |pattern: &Option<String>, value: &Option<String>| match pattern {
+            Some(pattern) => value.as_deref().is_some_and(|v| glob_match(pattern, v)),
+            None => true,
+        };
+        matches(&rule.app_id, &self.app_id)
+            && matches(&rule.title, &self.title)
+            && matches(&rule.class, &self.class)
+            && matches(&rule.instance, &self.instance)
+    }
+}
+
+impl ThingState {
+    /// Evaluates the rules for `window`.
+    ///
+    /// Its initial size is requested right away if it is not shown yet and the size changed, the
+    /// other settings about how it is shown are applied by [`Self::place_window`].
+    pub fn apply_window_rules(&mut self, window: &Window) {
+        let properties = WindowProperties::of(window);
+        let mut settings = RuleSettings::default();
+        for rule in self.config.rules.iter().filter(|r| properties.matches(r)) {
+            settings.apply(rule);
+        }
+
+        if let (Some(size), Some(toplevel)) = (settings.size, window.toplevel()) {
+            let changed = self.unplaced_windows.contains(window)
+                && toplevel.with_pending_state(|state| state.size.replace(size) != Some(size));
+            if changed {
+                send_configure(toplevel);
+            }
+        }
+
+        window_state(window).rules = settings;
+        self.queue_redraw_for_window(window);
+    }
+
+    /// Opacity of `window`, from 0 for invisible to 1 for opaque
+    pub fn window_opacity(&self, window: &Window) -> f32 {
+        window_state(window).rules.opacity.unwrap_or(1.0)
+    }
+
+    /// Evaluates the rules again for every window, once they changed
+    pub fn reapply_window_rules(&mut self) {
+        let windows: Vec<Window> = self
+            .space
+            .elements()
+            .cloned()
+            .chain(self.hidden_windows())
+            .chain(self.unmapped_x11_windows.iter().cloned())
+            .collect();
+        for window in &windows {
+            self.apply_window_rules(window);
+        }
+    }
+}
+
+/// Whether `window` can get the keyboard focus, which the rules can prevent, and which menus and
+/// tooltips of X11 clients never get
+pub fn accepts_focus(window: &Window) -> bool {
+    let override_redirect = window
+        .x11_surface()
+        .is_some_and(|surface| surface.is_override_redirect());
+    !override_redirect && !window_state(window).rules.never_focus
+}
+
+/// Whether `value` matches `pattern` entirely, `*` matching any sequence of characters
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // There is always a first part, empty if the pattern starts with `*`
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_without_star() {
+        assert!(glob_match("firefox", "firefox"));
+        assert!(glob_match("", ""));
+        assert!(!glob_match("firefox", "firefox-esr"));
+        assert!(!glob_match("firefox", "fire"));
+        assert!(!glob_match("", "firefox"));
+    }
+
+    #[test]
+    fn glob_with_stars() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("Grafana*", "Grafana - Dashboards"));
+        assert!(glob_match("*Dashboards", "Grafana - Dashboards"));
+        assert!(glob_match("*-*", "Grafana - Dashboards"));
+        assert!(glob_match("G*a*s", "Grafana - Dashboards"));
+        assert!(glob_match("a**b", "ab"));
+        assert!(!glob_match("Grafana*", "My Grafana"));
+        assert!(!glob_match("*Grafana", "Grafana - Dashboards"));
+        assert!(!glob_match("a*b*c", "acb"));
+    }
+
+    #[test]
+    fn glob_parts_do_not_overlap() {
+        assert!(!glob_match("ab*ba", "aba"));
+        assert!(glob_match("ab*ba", "abba"));
+        assert!(!glob_match("a*a", "a"));
+    }
+}
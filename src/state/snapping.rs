@@ -0,0 +1,301 @@
+use smithay::{
+    backend::renderer::element::{
+        solid::{SolidColorBuffer, SolidColorRenderElement},
+        Kind,
+    },
+    desktop::Window,
+    output::Output,
+    utils::{Logical, Point, Rectangle},
+};
+
+use super::{
+    tiling::set_tiled,
+    window::{send_configure, window_state},
+    ThingState,
+};
+
+/// Distance from the edge of an output under which the pointer offers to tile the dragged
+/// window, in logical pixels
+const EDGE_ZONE: f64 = 8.0;
+
+/// Area a window dragged to the edge of an output takes once dropped, drawn while it is dragged
+#[derive(Debug)]
+pub struct SnapPreview {
+    pub output: Output,
+    pub rect: Rectangle<i32, Logical>,
+    buffer: SolidColorBuffer,
+}
+
+impl ThingState {
+    /// Location in the space of `window`, moved to `loc`, once held back by the edges of the
+    /// outputs and snapped to the edges of the outputs and of the other windows
+    pub fn snap_location(
+        &self,
+        window: &Window,
+        mut loc: Point<i32, Logical>,
+    ) -> Point<i32, Logical> {
+        let size = window.geometry().size;
+        let threshold = self.config.snapping.threshold;
+        let resistance = self.config.snapping.edge_resistance;
+        let areas: Vec<Rectangle<i32, Logical>> = self
+            .space
+            .outputs()
+            .filter_map(|output| self.usable_area(output))
+            .collect();
+
+        if resistance > 0 {
+            for area in &areas {
+                if overlaps(loc.y, size.h, area.loc.y, area.size.h, 0) {
+                    loc.x = resist(loc.x, size.w, area.loc.x, area.size.w, resistance);
+                }
+                if overlaps(loc.x, size.w, area.loc.x, area.size.w, 0) {
+                    loc.y = resist(loc.y, size.h, area.loc.y, area.size.h, resistance);
+                }
+            }
+        }
+
+        if threshold > 0 {
+            let others: Vec<Rectangle<i32, Logical>> = self
+                .space
+                .elements()
+                .filter(|w| *w != window)
+                .filter_map(|w| self.space.element_geometry(w))
+                .filter(|geo| !geo.size.is_empty())
+                .collect();
+
+            // Locations where an edge of the window touches an edge of an output or window
+            let mut xs = Vec::new();
+            let mut ys = Vec::new();
+            for area in &areas {
+                xs.extend([area.loc.x, area.loc.x + area.size.w - size.w]);
+                ys.extend([area.loc.y, area.loc.y + area.size.h - size.h]);
+            }
+            // Only the windows next to the moved one, along the other axis
+            for other in &others {
+                let (left, right) = (other.loc.x, other.loc.x + other.size.w);
+                let (top, bottom) = (other.loc.y, other.loc.y + other.size.h);
+                if overlaps(loc.y, size.h, top, other.size.h, threshold) {
+                    xs.extend([left, right, left - size.w, right - size.w]);
+                }
+                if overlaps(loc.x, size.w, left, other.size.w, threshold) {
+                    ys.extend([top, bottom, top - size.h, bottom - size.h]);
+                }
+            }
+
+            loc.x = snap(loc.x, &xs, threshold);
+            loc.y = snap(loc.y, &ys, threshold);
+        }
+
+        loc
+    }
+
+    /// Output the pointer is on and the part of it a window dropped at `pointer` takes, if the
+    /// pointer is on one of its edges
+    pub fn edge_tile_area(
+        &self,
+        pointer: Point<f64, Logical>,
+    ) -> Option<(Output, Rectangle<i32, Logical>)> {
+        if !self.config.snapping.edge_tiling {
+            return None;
+        }
+        let output = self.space.output_under(pointer).next()?.clone();
+        let area = self.usable_area(&output)?;
+
+        // Edges shared with another output are crossed rather than tiled on
+        let is_edge = |offset: (f64, f64)| {
+            self.space
+                .output_under(pointer + Point::from(offset))
+                .next()
+                .is_none()
+        };
+        let p = pointer - area.loc.to_f64();
+        let (width, height) = (area.size.w as f64, area.size.h as f64);
+        let left = p.x < EDGE_ZONE && is_edge((-EDGE_ZONE, 0.0));
+        let right = p.x >= width - EDGE_ZONE && is_edge((EDGE_ZONE, 0.0));
+        let top = p.y < EDGE_ZONE && is_edge((0.0, -EDGE_ZONE));
+        let bottom = p.y >= height - EDGE_ZONE && is_edge((0.0, EDGE_ZONE));
+        if !(left || right || top || bottom) {
+            return None;
+        }
+
+        // The corners reach a quarter of the output along each edge
+        let left = left || ((top || bottom) && p.x < width / 4.0);
+        let right = right || ((top || bottom) && p.x >= width * 3.0 / 4.0);
+        let top = top || ((left || right) && p.y < height / 4.0);
+        let bottom = bottom || ((left || right) && p.y >= height * 3.0 / 4.0);
+
+        let (half_w, half_h) = (area.size.w / 2, area.size.h / 2);
+        let mut rect = area;
+        if left {
+            rect.size.w = half_w;
+        } else if right {
+            rect.loc.x += half_w;
+            rect.size.w -= half_w;
+        }
+        if top {
+            rect.size.h = half_h;
+        } else if bottom {
+            rect.loc.y += half_h;
+            rect.size.h -= half_h;
+        }
+        Some((output, rect))
+    }
+
+    /// Gives `window` the area `rect` of the edge it was dropped on, with the tiled states, until
+    /// it gets moved again, see [`Self::untile_from_edge`]
+    pub fn tile_on_edge(&mut self, window: &Window, rect: Rectangle<i32, Logical>) {
+        self.save_geometry(window);
+        window_state(window).edge_tiled = true;
+        set_tiled(window, true);
+        self.configure_window(window, rect.loc, Some(rect.size));
+    }
+
+    /// Takes `window` out of the edge it was tiled on, if it was.
+    ///
+    /// When it starts being moved from `anchor`, it gets back its size from before, keeping
+    /// `anchor` at the same place relative to it, otherwise it keeps its current geometry.
+    pub fn untile_from_edge(&mut self, window: &Window, anchor: Option<Point<f64, Logical>>) {
+        let restore_geometry = {
+            let mut state = window_state(window);
+            if !std::mem::take(&mut state.edge_tiled) {
+                return;
+            }
+            state.restore_geometry.take()
+        };
+        let untiled = set_tiled(window, false);
+
+        let current = self
+            .space
+            .element_geometry(window)
+            .filter(|geo| !geo.size.is_empty());
+        let (Some(anchor), Some(saved), Some(current)) = (anchor, restore_geometry, current) else {
+            if let (true, Some(toplevel)) = (untiled, window.toplevel()) {
+                send_configure(toplevel);
+            }
+            return;
+        };
+        let ratio_x = ((anchor.x - current.loc.x as f64) / current.size.w as f64).clamp(0.0, 1.0);
+        let ratio_y = ((anchor.y - current.loc.y as f64) / current.size.h as f64).clamp(0.0, 1.0);
+        let loc = Point::from((
+            anchor.x - ratio_x * saved.size.w as f64,
+            anchor.y - ratio_y * saved.size.h as f64,
+        ));
+        self.configure_window(window, loc.to_i32_round(), Some(saved.size));
+    }
+
+    /// Shows `area` as the one the dragged window takes once dropped, or nothing if `None`
+    pub fn set_snap_preview(&mut self, area: Option<(Output, Rectangle<i32, Logical>)>) {
+        let current = self.snap_preview.as_ref().map(|p| (&p.output, p.rect));
+        if current == area.as_ref().map(|(output, rect)| (output, *rect)) {
+            return;
+        }
+
+        if let Some(preview) = self.snap_preview.take() {
+            self.render_scheduler.queue_redraw(&preview.output);
+        }
+        let Some((output, rect)) = area else {
+            return;
+        };
+        let [r, g, b, a] = self.config.snapping.preview_color.0;
+        let buffer = SolidColorBuffer::new(rect.size, [r * a, g * a, b * a, a]);
+        self.render_scheduler.queue_redraw(&output);
+        self.snap_preview = Some(SnapPreview {
+            output,
+            rect,
+            buffer,
+        });
+    }
+
+    /// Element drawing the area the dragged window takes once dropped, if it is on `output`
+    pub fn snap_preview_element(&self, output: &Output) -> Option<SolidColorRenderElement> {
+        let preview = self.snap_preview.as_ref().filter(|p| p.output == *output)?;
+        let output_geo = self.space.output_geometry(output)?;
+
+        let scale = output.current_scale().fractional_scale();
+        let loc = (preview.rect.loc - output_geo.loc)
+            .to_f64()
+            .to_physical(scale)
+            .to_i32_round();
+        Some(SolidColorRenderElement::from_buffer(
+            &preview.buffer,
+            loc,
+            scale,
+            1.0,
+            Kind::Unspecified,
+        ))
+    }
+}
+
+/// Whether the segment of `len` starting at `pos` overlaps the one of `other_len` starting at
+/// `other`, or comes closer to it than `margin`
+fn overlaps(pos: i32, len: i32, other: i32, other_len: i32, margin: i32) -> bool {
+    pos < other + other_len + margin && other < pos + len + margin
+}
+
+/// Position of a segment of `len` moved to `pos`, kept inside the area of `area_len` starting at
+/// `start` until it goes past one of its ends by more than `resistance`
+fn resist(pos: i32, len: i32, start: i32, area_len: i32, resistance: i32) -> i32 {
+    let end = start + area_len;
+    if pos < start && pos > start - resistance {
+        start
+    } else if pos + len > end && pos + len < end + resistance {
+        end - len
+    } else {
+        pos
+    }
+}
+
+/// The candidate closest to `pos`, if it is no further than `threshold`
+fn snap(pos: i32, candidates: &[i32], threshold: i32) -> i32 {
+    candidates
+        .iter()
+        .copied()
+        .min_by_key(|c| (c - pos).abs())
+        .filter(|c| (c - pos).abs() <= threshold)
+        .unwrap_or(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snap_to_the_closest_candidate() {
+        assert_eq!(snap(103, &[0, 100, 105], 10), 105);
+        assert_eq!(snap(97, &[0, 100, 105], 10), 100);
+        assert_eq!(snap(-4, &[0, 100], 10), 0);
+    }
+
+    #[test]
+    fn snap_within_the_threshold_only() {
+        assert_eq!(snap(110, &[100], 10), 100);
+        assert_eq!(snap(111, &[100], 10), 111);
+        assert_eq!(snap(50, &[], 10), 50);
+    }
+
+    #[test]
+    fn resist_at_both_ends() {
+        // A segment of 100 in the area from 0 to 1000, with a resistance of 20
+        assert_eq!(resist(-10, 100, 0, 1000, 20), 0);
+        assert_eq!(resist(-20, 100, 0, 1000, 20), -20);
+        assert_eq!(resist(910, 100, 0, 1000, 20), 900);
+        assert_eq!(resist(920, 100, 0, 1000, 20), 920);
+    }
+
+    #[test]
+    fn resist_leaves_segments_inside_alone() {
+        assert_eq!(resist(0, 100, 0, 1000, 20), 0);
+        assert_eq!(resist(450, 100, 0, 1000, 20), 450);
+        assert_eq!(resist(900, 100, 0, 1000, 20), 900);
+        // Segments far outside already crossed the edge
+        assert_eq!(resist(-500, 100, 0, 1000, 20), -500);
+    }
+
+    #[test]
+    fn overlaps_with_margin() {
+        assert!(overlaps(0, 100, 50, 100, 0));
+        assert!(!overlaps(0, 100, 100, 100, 0));
+        assert!(overlaps(0, 100, 105, 100, 10));
+        assert!(!overlaps(0, 100, 110, 100, 10));
+    }
+}
@@ -0,0 +1,81 @@
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+use smithay::reexports::calloop::channel::{self, Event};
+use tracing::{error, info};
+
+use super::ThingState;
+
+/// Command run once the compositor is ready
+#[derive(Debug, Clone)]
+pub struct StartupCommand {
+    /// Command to run, with `sh -c`
+    pub command: String,
+    /// Wait for XWayland to be ready before running the command
+    pub wait_for_xwayland: bool,
+    /// Stop the compositor once the command exits, see [`ThingState::exit_code`]
+    pub exit_with: bool,
+}
+
+impl ThingState {
+    /// Runs the startup command, if it has not been run yet.
+    ///
+    /// If the command waits for XWayland and it is not ready yet, this does nothing: the command
+    /// will be run once XWayland is ready.
+    pub fn run_startup_command(&mut self) {
+        let Some(startup) = self.startup_command.as_ref() else {
+            return;
+        };
+        if startup.wait_for_xwayland && self.xdisplay.is_some() && self.xwm.is_none() {
+            return;
+        }
+        let startup = self.startup_command.take().unwrap();
+
+        let mut child = match self.spawn(&startup.command) {
+            Ok(child) => child,
+            Err(err) => {
+                error!(?err, "Unable to spawn `{}`", startup.command);
+                if startup.exit_with {
+                    self.exit_code = Some(1);
+                    self.loop_signal.stop();
+                }
+                return;
+            }
+        };
+        info!("Spawned `{}`", startup.command);
+
+        if !startup.exit_with {
+            // Reap the process once it exits
+            std::thread::spawn(move || child.wait());
+            return;
+        }
+
+        let (sender, channel) = channel::channel::<std::io::Result<ExitStatus>>();
+        let res = self.loop_handle.insert_source(channel, move |event, _, data| {
+            let Event::Msg(status) = event else {
+                return;
+            };
+            let code = match status {
+                Ok(status) => {
+                    info!(%status, "Startup command exited");
+                    status
+                        .code()
+                        .or_else(|| status.signal().map(|signal| 128 + signal))
+                        .unwrap_or(1)
+                }
+                Err(err) => {
+                    error!(?err, "Unable to wait for the startup command");
+                    1
+                }
+            };
+            data.state.exit_code = Some(code);
+            data.state.loop_signal.stop();
+        });
+        if let Err(err) = res {
+            error!(?err, "Unable to watch the startup command");
+        }
+
+        std::thread::spawn(move || {
+            let _ = sender.send(child.wait());
+        });
+    }
+}
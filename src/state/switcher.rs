@@ -0,0 +1,494 @@
+use std::{ffi::OsString, fs, os::unix::ffi::OsStringExt, path::PathBuf, process::Command};
+
+use fontdue::{Font, FontSettings};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        input::KeyState,
+        renderer::{
+            element::{
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                Kind,
+            },
+            ImportMem, Renderer,
+        },
+    },
+    desktop::Window,
+    input::keyboard::{GrabStartData, KeyboardGrab, KeyboardInnerHandle, Keycode, ModifiersState},
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Buffer, Logical, Point, Rectangle, Serial, Size, Transform},
+    wayland::{compositor::with_states, seat::WaylandFocus, shell::xdg::XdgToplevelSurfaceData},
+};
+use tracing::{error, warn};
+
+use crate::config::bindings::Modifiers;
+
+use super::{keycodes::KEY_ESCAPE, rules::accepts_focus, ThingState};
+
+/// Colours of the switcher, as RGBA
+const BACKGROUND_COLOR: [u8; 4] = [0x22, 0x22, 0x22, 0xee];
+const SELECTION_COLOR: [u8; 4] = [0x3d, 0x5a, 0x8c, 0xff];
+const TEXT_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// Window switcher, open while the modifiers of the binding which opened it are held
+pub struct Switcher {
+    /// Windows from the most recently focused one
+    windows: Vec<Window>,
+    selected: usize,
+    /// Output the switcher is displayed on
+    output: Output,
+    /// Picture of the list of windows, drawn again when the selection changes
+    buffer: Option<MemoryRenderBuffer>,
+    size: Size<i32, Logical>,
+}
+
+impl Switcher {
+    fn select(&mut self, forward: bool) {
+        let len = self.windows.len();
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+}
+
+impl ThingState {
+    /// Opens the window switcher, or selects the next window in it if it is open
+    pub fn switch_windows(&mut self, forward: bool, serial: Serial) {
+        if let Some(switcher) = &mut self.switcher {
+            switcher.select(forward);
+            self.draw_switcher();
+            return;
+        }
+
+        let windows = self.windows_by_recent_use();
+        if windows.is_empty() {
+            return;
+        }
+        // The focused window is the first one, start from the one focused before it
+        let start = usize::from(self.focused_window().as_ref() == windows.first());
+        let selected = if forward {
+            start % windows.len()
+        } else {
+            windows.len() - 1
+        };
+
+        // Shift is usually what goes backward, releasing it does not close the switcher
+        let modifiers =
+            Modifiers::from(&self.keyboard_handle.modifier_state()).difference(Modifiers::SHIFT);
+        if modifiers.is_empty() {
+            // Nothing to release, the window is focused right away
+            let window = windows[selected].clone();
+            self.focus_window(&window, serial);
+            return;
+        }
+
+        let output = self
+            .focused_window()
+            .and_then(|window| self.space.outputs_for_element(&window).into_iter().next())
+            .or_else(|| self.pointer_output());
+        let Some(output) = output else {
+            return;
+        };
+
+        self.load_switcher_font();
+        self.switcher = Some(Switcher {
+            windows,
+            selected,
+            output,
+            buffer: None,
+            size: Size::default(),
+        });
+        self.draw_switcher();
+
+        let grab = SwitcherGrab {
+            start_data: GrabStartData { focus: None },
+            modifiers,
+        };
+        let keyboard = self.keyboard_handle.clone();
+        keyboard.set_grab(self, grab, serial);
+    }
+
+    /// Element of the window switcher, if it is displayed on `output`
+    pub fn switcher_element<R>(
+        &self,
+        renderer: &mut R,
+        output: &Output,
+    ) -> Option<MemoryRenderBufferRenderElement<R>>
+    where
+        R: Renderer + ImportMem,
+        <R as Renderer>::TextureId: Send + Clone + 'static,
+    {
+        let switcher = self.switcher.as_ref().filter(|s| s.output == *output)?;
+        let buffer = switcher.buffer.as_ref()?;
+        let output_geo = self.space.output_geometry(output)?;
+
+        // Centred on the output
+        let loc = Point::<i32, Logical>::from((
+            (output_geo.size.w - switcher.size.w) / 2,
+            (output_geo.size.h - switcher.size.h) / 2,
+        ));
+        let loc = loc
+            .to_f64()
+            .to_physical(output.current_scale().fractional_scale());
+        MemoryRenderBufferRenderElement::from_buffer(
+            renderer,
+            loc,
+            buffer,
+            None,
+            None,
+            None,
+            Kind::Unspecified,
+        )
+        .map_err(|err| error!(?err, "Unable to render the window switcher"))
+        .ok()
+    }
+
+    /// Windows in the space which can be focused, from the most recently focused one
+    fn windows_by_recent_use(&self) -> Vec<Window> {
+        let mut windows: Vec<Window> = self
+            .focus_stack
+            .iter()
+            .rev()
+            .filter(|w| self.space.element_location(w).is_some())
+            .cloned()
+            .collect();
+        // Windows which were never focused come last, from the top-most one
+        let never_focused: Vec<Window> = self
+            .space
+            .elements()
+            .filter(|w| !windows.contains(w) && accepts_focus(w))
+            .cloned()
+            .collect();
+        windows.extend(never_focused.into_iter().rev());
+        windows
+    }
+
+    /// Loads the font of the window switcher, unless it was already loaded, or failed to, for the
+    /// configured path
+    fn load_switcher_font(&mut self) {
+        let configured = self.config.switcher.font.clone();
+        if self
+            .switcher_font
+            .as_ref()
+            .is_some_and(|(loaded, _)| *loaded == configured)
+        {
+            return;
+        }
+        let Some(path) = configured.clone().or_else(default_font) else {
+            self.switcher_font = Some((configured, None));
+            return warn!("No font found, set `switcher.font` to show the window titles");
+        };
+
+        let font = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| {
+                Font::from_bytes(bytes, FontSettings::default()).map_err(str::to_string)
+            });
+        let font = match font {
+            Ok(font) => Some(font),
+            Err(err) => {
+                error!("Unable to load font {}: {}", path.display(), err);
+                None
+            }
+        };
+        self.switcher_font = Some((configured, font));
+    }
+
+    /// Draws the list of windows of the switcher again
+    fn draw_switcher(&mut self) {
+        let Some(switcher) = &self.switcher else {
+            return;
+        };
+        let titles: Vec<String> = switcher.windows.iter().map(window_title).collect();
+        let scale = switcher.output.current_scale().integer_scale();
+        // Long titles are cut to keep the switcher in the output
+        let max_width = self
+            .space
+            .output_geometry(&switcher.output)
+            .map(|geo| geo.size.w * 4 / 5)
+            .unwrap_or(i32::MAX);
+
+        let canvas = draw_list(
+            self.switcher_font
+                .as_ref()
+                .and_then(|(_, font)| font.as_ref()),
+            &titles,
+            switcher.selected,
+            self.config.switcher.font_size * scale as f32,
+            max_width.saturating_mul(scale),
+        );
+        let buffer = MemoryRenderBuffer::from_slice(
+            &canvas.pixels,
+            Fourcc::Argb8888,
+            (canvas.width, canvas.height),
+            scale,
+            Transform::Normal,
+            None,
+        );
+
+        let output = switcher.output.clone();
+        if let Some(switcher) = &mut self.switcher {
+            switcher.buffer = Some(buffer);
+            switcher.size = (canvas.width / scale, canvas.height / scale).into();
+        }
+        self.render_scheduler.queue_redraw(&output);
+    }
+}
+
+/// File of the default sans-serif font, as given by fontconfig
+fn default_font() -> Option<PathBuf> {
+    let output = Command::new("fc-match")
+        .args(["--format=%{file}", "sans-serif"])
+        .output();
+    match output {
+        Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+            Some(PathBuf::from(OsString::from_vec(output.stdout)))
+        }
+        Ok(output) => {
+            error!(status = %output.status, "fc-match found no sans-serif font");
+            None
+        }
+        Err(err) => {
+            error!(?err, "Unable to run fc-match to find a font");
+            None
+        }
+    }
+}
+
+/// Keyboard grab of the window switcher, focusing the selected window when the modifiers are
+/// released
+struct SwitcherGrab {
+    start_data: GrabStartData<ThingState>,
+    /// Modifiers held when the switcher was opened
+    modifiers: Modifiers,
+}
+
+impl KeyboardGrab<ThingState> for SwitcherGrab {
+    fn input(
+        &mut self,
+        data: &mut ThingState,
+        handle: &mut KeyboardInnerHandle<'_, ThingState>,
+        keycode: Keycode,
+        state: KeyState,
+        modifiers: Option<ModifiersState>,
+        serial: Serial,
+        time: u32,
+    ) {
+        // Closes the switcher without changing the focus
+        if state == KeyState::Pressed && keycode.raw() == KEY_ESCAPE {
+            handle.unset_grab(self, data, serial, false);
+            return;
+        }
+        // Only modifiers are sent to clients, so they know when they are released
+        let Some(modifiers) = modifiers else {
+            return;
+        };
+        handle.input(data, keycode, state, Some(modifiers), serial, time);
+
+        if !Modifiers::from(&modifiers).contains(self.modifiers) {
+            let window = data
+                .switcher
+                .as_ref()
+                .map(|switcher| switcher.windows[switcher.selected].clone());
+            handle.unset_grab(self, data, serial, false);
+
+            // It may have been closed meanwhile
+            if let Some(window) = window.filter(|w| data.space.element_location(w).is_some()) {
+                data.space.raise_element(&window, false);
+                handle.set_focus(data, window.wl_surface().map(|s| s.into_owned()), serial);
+            }
+        }
+    }
+
+    fn set_focus(
+        &mut self,
+        data: &mut ThingState,
+        handle: &mut KeyboardInnerHandle<'_, ThingState>,
+        focus: Option<WlSurface>,
+        serial: Serial,
+    ) {
+        handle.set_focus(data, focus, serial);
+    }
+
+    fn start_data(&self) -> &GrabStartData<ThingState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, data: &mut ThingState) {
+        if let Some(switcher) = data.switcher.take() {
+            data.render_scheduler.queue_redraw(&switcher.output);
+        }
+    }
+}
+
+/// Title of `window`, or the name of its application if it has none
+fn window_title(window: &Window) -> String {
+    let title = if let Some(toplevel) = window.toplevel() {
+        with_states(toplevel.wl_surface(), |states| {
+            let data = states
+                .data_map
+                .get::<XdgToplevelSurfaceData>()?
+                .lock()
+                .ok()?;
+            data.title
+                .clone()
+                .filter(|title| !title.is_empty())
+                .or_else(|| data.app_id.clone())
+        })
+    } else if let Some(surface) = window.x11_surface() {
+        Some(surface.title())
+            .filter(|title| !title.is_empty())
+            .or_else(|| Some(surface.class()))
+    } else {
+        None
+    };
+    title
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// Pixels in the ARGB8888 format, with premultiplied alpha
+struct Canvas {
+    pixels: Vec<u8>,
+    width: i32,
+    height: i32,
+}
+
+impl Canvas {
+    fn new(width: i32, height: i32) -> Self {
+        Self {
+            pixels: vec![0; (width * height * 4) as usize],
+            width,
+            height,
+        }
+    }
+
+    /// Blends `color` over the pixel at (`x`, `y`), with `coverage` from 0 to 255
+    fn blend(&mut self, x: i32, y: i32, color: [u8; 4], coverage: u8) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let alpha = color[3] as u32 * coverage as u32 / 255;
+        let index = ((y * self.width + x) * 4) as usize;
+        let pixel = &mut self.pixels[index..index + 4];
+        // Little endian ARGB, so BGRA in memory
+        let src = [color[2], color[1], color[0], 0xff];
+        for (dst, src) in pixel.iter_mut().zip(src) {
+            *dst = ((src as u32 * alpha + *dst as u32 * (255 - alpha)) / 255) as u8;
+        }
+    }
+
+    fn fill(&mut self, rect: Rectangle<i32, Buffer>, color: [u8; 4]) {
+        for y in rect.loc.y..rect.loc.y + rect.size.h {
+            for x in rect.loc.x..rect.loc.x + rect.size.w {
+                self.blend(x, y, color, 255);
+            }
+        }
+    }
+
+    /// Draws `text` with its baseline starting at (`x`, `baseline`)
+    fn draw_text(&mut self, font: &Font, text: &str, size: f32, x: i32, baseline: i32) {
+        let mut pen = x as f32;
+        for c in text.chars() {
+            let (metrics, coverage) = font.rasterize(c, size);
+            let left = pen.round() as i32 + metrics.xmin;
+            let top = baseline - metrics.ymin - metrics.height as i32;
+            for (i, coverage) in coverage.into_iter().enumerate() {
+                let glyph_x = (i % metrics.width.max(1)) as i32;
+                let glyph_y = (i / metrics.width.max(1)) as i32;
+                self.blend(left + glyph_x, top + glyph_y, TEXT_COLOR, coverage);
+            }
+            pen += metrics.advance_width;
+        }
+    }
+}
+
+fn text_width(font: &Font, text: &str, size: f32) -> i32 {
+    text.chars()
+        .map(|c| font.metrics(c, size).advance_width)
+        .sum::<f32>()
+        .ceil() as i32
+}
+
+/// Cuts `text` with an ellipsis so that it is at most `max_width` wide
+fn truncate(font: &Font, text: &str, size: f32, max_width: i32) -> String {
+    if text_width(font, text, size) <= max_width {
+        return text.to_string();
+    }
+    let mut truncated: String = text.to_string();
+    while !truncated.is_empty() {
+        truncated.pop();
+        let candidate = format!("{}…", truncated);
+        if text_width(font, &candidate, size) <= max_width {
+            return candidate;
+        }
+    }
+    String::new()
+}
+
+/// Draws `titles` one below the other, highlighting the `selected` one.
+///
+/// Without font, only the highlighted row is visible.
+fn draw_list(
+    font: Option<&Font>,
+    titles: &[String],
+    selected: usize,
+    font_size: f32,
+    max_width: i32,
+) -> Canvas {
+    let padding = (font_size / 2.0).ceil() as i32;
+    let row_height = (font_size * 1.6).ceil() as i32;
+    let max_text_width = max_width - padding * 4;
+
+    let lines: Vec<String> = match font {
+        Some(font) => titles
+            .iter()
+            .map(|title| truncate(font, title, font_size, max_text_width))
+            .collect(),
+        None => Vec::new(),
+    };
+    let text_width = font
+        .and_then(|font| {
+            lines
+                .iter()
+                .map(|line| text_width(font, line, font_size))
+                .max()
+        })
+        .unwrap_or(0);
+
+    let width = (text_width + padding * 4)
+        .max((font_size * 20.0) as i32)
+        .min(max_width);
+    let height = titles.len() as i32 * row_height + padding * 2;
+    let mut canvas = Canvas::new(width, height);
+
+    canvas.fill(
+        Rectangle::from_loc_and_size((0, 0), (width, height)),
+        BACKGROUND_COLOR,
+    );
+    canvas.fill(
+        Rectangle::from_loc_and_size(
+            (padding, padding + selected as i32 * row_height),
+            (width - padding * 2, row_height),
+        ),
+        SELECTION_COLOR,
+    );
+
+    let Some(font) = font else {
+        return canvas;
+    };
+    let (ascent, descent) = font
+        .horizontal_line_metrics(font_size)
+        .map(|metrics| (metrics.ascent, metrics.descent))
+        .unwrap_or((font_size, 0.0));
+    // Vertically centred in the row, the descent being negative
+    let baseline_offset = ((row_height as f32 - (ascent - descent)) / 2.0 + ascent).round() as i32;
+    for (i, line) in lines.iter().enumerate() {
+        let top = padding + i as i32 * row_height;
+        canvas.draw_text(font, line, font_size, padding * 2, top + baseline_offset);
+    }
+    canvas
+}
@@ -0,0 +1,429 @@
+use smithay::{
+    desktop::Window,
+    output::Output,
+    reexports::wayland_protocols::xdg::shell::server::xdg_toplevel::State,
+    utils::{Logical, Rectangle},
+};
+
+use crate::config::{Layout, TilingConfig};
+
+use super::{
+    window::{send_configure, window_state},
+    ThingState,
+};
+
+/// States of `xdg_toplevel` telling a window that its edges touch something
+const TILED_STATES: [State; 4] = [
+    State::TiledLeft,
+    State::TiledRight,
+    State::TiledTop,
+    State::TiledBottom,
+];
+
+impl ThingState {
+    /// Arranges the tiled windows of the active workspace of `output` according to the current
+    /// layout
+    pub fn retile(&mut self, output: &Output) {
+        if self.layout == Layout::Floating {
+            return;
+        }
+        let Some(area) = self.usable_area(output) else {
+            return;
+        };
+
+        let windows: Vec<Window> = self
+            .tiling_order
+            .iter()
+            .filter(|w| self.space.outputs_for_element(w).first() == Some(output))
+            .filter(|w| self.is_tiled(w))
+            .cloned()
+            .collect();
+
+        let tiles = layout_tiles(self.layout, area, windows.len(), &self.config.tiling);
+        for (window, tile) in windows.iter().zip(tiles) {
+            set_tiled(window, true);
+            self.configure_window(window, tile.loc, Some(tile.size));
+        }
+    }
+
+    /// Arranges the tiled windows of every output
+    pub fn retile_all(&mut self) {
+        let outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        for output in &outputs {
+            self.retile(output);
+        }
+    }
+
+    /// Arranges the tiled windows of the output `window` is on
+    pub fn retile_window_output(&mut self, window: &Window) {
+        let output = self.space.outputs_for_element(window).into_iter().next();
+        if let Some(output) = output {
+            self.retile(&output);
+        }
+    }
+
+    /// Switches to `layout`, arranging the windows of every output
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        if layout == Layout::Floating {
+            // The windows stay where they are, without being told they are tiled
+            for window in &self.tiling_order {
+                if set_tiled(window, false) {
+                    if let Some(toplevel) = window.toplevel() {
+                        send_configure(toplevel);
+                    }
+                }
+            }
+        }
+        self.retile_all();
+    }
+
+    /// Takes `window` out of the tiling layout, keeping its current geometry
+    pub fn float_window(&mut self, window: &Window) {
+        let was_tiled = {
+            let mut state = window_state(window);
+            !std::mem::replace(&mut state.floating, true)
+        };
+        if !was_tiled {
+            return;
+        }
+
+        if set_tiled(window, false) {
+            if let Some(toplevel) = window.toplevel() {
+                send_configure(toplevel);
+            }
+        }
+        self.space.raise_element(window, false);
+        self.retile_window_output(window);
+    }
+
+    /// Whether `window` is arranged by the current layout
+    pub fn is_tiled(&self, window: &Window) -> bool {
+        let state = window_state(window);
+        self.layout != Layout::Floating && !state.floating && !state.maximized && !state.fullscreen
+    }
+
+    /// Makes `window` floating if it is tiled, and tiles it otherwise
+    pub fn toggle_floating(&mut self, window: &Window) {
+        if window_state(window).floating {
+            window_state(window).floating = false;
+            self.retile_window_output(window);
+        } else {
+            self.float_window(window);
+        }
+    }
+}
+
+/// Sets or unsets the tiled states of `window`, returns whether they changed
+pub fn set_tiled(window: &Window, tiled: bool) -> bool {
+    let Some(toplevel) = window.toplevel() else {
+        return false;
+    };
+    let mut changed = false;
+    toplevel.with_pending_state(|state| {
+        for tiled_state in TILED_STATES {
+            changed |= if tiled {
+                state.states.set(tiled_state)
+            } else {
+                state.states.unset(tiled_state)
+            };
+        }
+    });
+    changed
+}
+
+/// Geometries of `count` windows tiled in `area` by `layout`, with the configured gaps around
+/// them.
+///
+/// Like the locations of the space, they exclude client-side decorations, which may go past them.
+fn layout_tiles(
+    layout: Layout,
+    area: Rectangle<i32, Logical>,
+    count: usize,
+    config: &TilingConfig,
+) -> Vec<Rectangle<i32, Logical>> {
+    let half_gap = config.gap / 2;
+    let area = inset(area, half_gap);
+    let tiles = match layout {
+        Layout::Floating => return Vec::new(),
+        Layout::MasterStack => master_stack(area, count, config.master_ratio),
+        Layout::BinarySplit => binary_split(area, count),
+    };
+    tiles
+        .into_iter()
+        .map(|tile| inset(tile, half_gap))
+        .collect()
+}
+
+/// Tiles of [`Layout::MasterStack`] for `count` windows in `area`
+fn master_stack(
+    area: Rectangle<i32, Logical>,
+    count: usize,
+    ratio: f64,
+) -> Vec<Rectangle<i32, Logical>> {
+    if count <= 1 {
+        return vec![area; count];
+    }
+
+    let master_width = (area.size.w as f64 * ratio).round() as i32;
+    let mut tiles = vec![Rectangle::from_loc_and_size(
+        area.loc,
+        (master_width, area.size.h),
+    )];
+
+    let stack = count as i32 - 1;
+    let x = area.loc.x + master_width;
+    let width = area.size.w - master_width;
+    for i in 0..stack {
+        let top = area.size.h * i / stack;
+        let bottom = area.size.h * (i + 1) / stack;
+        tiles.push(Rectangle::from_loc_and_size(
+            (x, area.loc.y + top),
+            (width, bottom - top),
+        ));
+    }
+    tiles
+}
+
+/// Tiles of [`Layout::BinarySplit`] for `count` windows in `area`
+fn binary_split(area: Rectangle<i32, Logical>, count: usize) -> Vec<Rectangle<i32, Logical>> {
+    let mut tiles = Vec::with_capacity(count);
+    let mut rest = area;
+    for i in 0..count {
+        if i == count - 1 {
+            tiles.push(rest);
+            break;
+        }
+
+        let (tile, next) = if rest.size.w >= rest.size.h {
+            let width = rest.size.w / 2;
+            (
+                Rectangle::from_loc_and_size(rest.loc, (width, rest.size.h)),
+                Rectangle::from_loc_and_size(
+                    (rest.loc.x + width, rest.loc.y),
+                    (rest.size.w - width, rest.size.h),
+                ),
+            )
+        } else {
+            let height = rest.size.h / 2;
+            (
+                Rectangle::from_loc_and_size(rest.loc, (rest.size.w, height)),
+                Rectangle::from_loc_and_size(
+                    (rest.loc.x, rest.loc.y + height),
+                    (rest.size.w, rest.size.h - height),
+                ),
+            )
+        };
+        tiles.push(tile);
+        rest = next;
+    }
+    tiles
+}
+
+/// Shrinks `rect` by `amount` on every side, keeping it at least one pixel wide and high and
+/// inside `rect`
+fn inset(rect: Rectangle<i32, Logical>, amount: i32) -> Rectangle<i32, Logical> {
+    // Rectangles smaller than twice the amount keep their center
+    let x = amount.min((rect.size.w - 1) / 2);
+    let y = amount.min((rect.size.h - 1) / 2);
+    Rectangle::from_loc_and_size(
+        (rect.loc.x + x, rect.loc.y + y),
+        (rect.size.w - 2 * x, rect.size.h - 2 * y),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use smithay::{
+        desktop::{space::SpaceElement, Space},
+        utils::{IsAlive, Point, Size},
+    };
+
+    use super::*;
+
+    /// Width of the shadows drawn around [`Decorated`] elements
+    const SHADOW: i32 = 12;
+
+    /// Element with client-side decorations, drawn past its geometry
+    #[derive(Debug, Clone, PartialEq)]
+    struct Decorated {
+        id: usize,
+        size: Size<i32, Logical>,
+    }
+
+    impl IsAlive for Decorated {
+        fn alive(&self) -> bool {
+            true
+        }
+    }
+
+    impl SpaceElement for Decorated {
+        fn geometry(&self) -> Rectangle<i32, Logical> {
+            Rectangle::from_loc_and_size((SHADOW, SHADOW), self.size)
+        }
+
+        fn bbox(&self) -> Rectangle<i32, Logical> {
+            Rectangle::from_loc_and_size(
+                (0, 0),
+                (self.size.w + 2 * SHADOW, self.size.h + 2 * SHADOW),
+            )
+        }
+
+        fn is_in_input_region(&self, _point: &Point<f64, Logical>) -> bool {
+            false
+        }
+
+        fn set_activate(&self, _activated: bool) {}
+
+        fn output_enter(&self, _output: &Output, _overlap: Rectangle<i32, Logical>) {}
+
+        fn output_leave(&self, _output: &Output) {}
+    }
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::from_loc_and_size((x, y), (w, h))
+    }
+
+    /// Whether `tiles` cover `area` exactly, without overlapping
+    fn covers(tiles: &[Rectangle<i32, Logical>], area: Rectangle<i32, Logical>) -> bool {
+        let surface: i32 = tiles.iter().map(|t| t.size.w * t.size.h).sum();
+        let overlap = tiles.iter().enumerate().any(|(i, a)| {
+            tiles[i + 1..]
+                .iter()
+                .any(|b| a.intersection(*b).is_some_and(|r| !r.is_empty()))
+        });
+        surface == area.size.w * area.size.h
+            && !overlap
+            && tiles.iter().all(|t| area.contains_rect(*t))
+    }
+
+    #[test]
+    fn master_stack_without_stack() {
+        let area = rect(10, 20, 800, 600);
+        assert!(master_stack(area, 0, 0.5).is_empty());
+        assert_eq!(master_stack(area, 1, 0.5), vec![area]);
+    }
+
+    #[test]
+    fn master_stack_splits_the_stack_evenly() {
+        let area = rect(10, 20, 800, 600);
+        assert_eq!(
+            master_stack(area, 4, 0.6),
+            vec![
+                rect(10, 20, 480, 600),
+                rect(490, 20, 320, 200),
+                rect(490, 220, 320, 200),
+                rect(490, 420, 320, 200),
+            ]
+        );
+    }
+
+    #[test]
+    fn master_stack_with_odd_sizes() {
+        let area = rect(0, 0, 101, 101);
+        let tiles = master_stack(area, 3, 0.5);
+        // The master width is rounded, the stack takes the rest
+        assert_eq!(tiles[0], rect(0, 0, 51, 101));
+        assert_eq!(tiles[1], rect(51, 0, 50, 50));
+        assert_eq!(tiles[2], rect(51, 50, 50, 51));
+        for count in 2..12 {
+            assert!(covers(&master_stack(area, count, 0.55), area));
+        }
+    }
+
+    #[test]
+    fn binary_split_without_split() {
+        let area = rect(10, 20, 800, 600);
+        assert!(binary_split(area, 0).is_empty());
+        assert_eq!(binary_split(area, 1), vec![area]);
+    }
+
+    #[test]
+    fn binary_split_halves_the_longest_side() {
+        let area = rect(0, 0, 800, 600);
+        assert_eq!(
+            binary_split(area, 4),
+            vec![
+                rect(0, 0, 400, 600),
+                rect(400, 0, 400, 300),
+                rect(400, 300, 200, 300),
+                rect(600, 300, 200, 300),
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_split_with_odd_sizes() {
+        let area = rect(0, 0, 101, 51);
+        assert_eq!(
+            binary_split(area, 2),
+            vec![rect(0, 0, 50, 51), rect(50, 0, 51, 51)]
+        );
+        for count in 2..12 {
+            assert!(covers(&binary_split(area, count), area));
+        }
+    }
+
+    #[test]
+    fn layout_tiles_keep_gaps() {
+        let config = TilingConfig {
+            layout: Layout::MasterStack,
+            master_ratio: 0.5,
+            gap: 10,
+        };
+        let area = rect(0, 0, 1000, 600);
+        assert_eq!(
+            layout_tiles(Layout::MasterStack, area, 3, &config),
+            vec![
+                rect(10, 10, 485, 580),
+                rect(505, 10, 485, 285),
+                rect(505, 305, 485, 285),
+            ]
+        );
+        assert!(layout_tiles(Layout::Floating, area, 3, &config).is_empty());
+    }
+
+    #[test]
+    fn decorated_windows_are_mapped_on_their_tiles() {
+        let config = TilingConfig {
+            layout: Layout::BinarySplit,
+            master_ratio: 0.5,
+            gap: 0,
+        };
+        let area = rect(0, 0, 800, 600);
+        let tiles = layout_tiles(Layout::BinarySplit, area, 3, &config);
+
+        // Like `ThingState::configure_window`, once the windows took the size of their tile
+        let mut space = Space::default();
+        for (id, tile) in tiles.iter().enumerate() {
+            let element = Decorated {
+                id,
+                size: tile.size,
+            };
+            space.map_element(element, tile.loc, false);
+        }
+
+        assert_eq!(space.elements().count(), tiles.len());
+        for element in space.elements() {
+            let geometry = space.element_geometry(element).unwrap();
+            assert_eq!(geometry, tiles[element.id]);
+            // Only the decorations go past the tile
+            let bbox = space.element_bbox(element).unwrap();
+            assert_eq!(bbox.loc, geometry.loc - Point::from((SHADOW, SHADOW)));
+        }
+    }
+
+    #[test]
+    fn inset_shrinks_every_side() {
+        assert_eq!(inset(rect(10, 20, 100, 50), 5), rect(15, 25, 90, 40));
+        assert_eq!(inset(rect(10, 20, 100, 50), 0), rect(10, 20, 100, 50));
+    }
+
+    #[test]
+    fn inset_larger_than_the_rect() {
+        assert_eq!(inset(rect(10, 20, 100, 9), 8), rect(18, 24, 84, 1));
+        assert_eq!(inset(rect(10, 20, 10, 100), 8), rect(14, 28, 2, 84));
+        let tiny = rect(10, 20, 1, 1);
+        assert_eq!(inset(tiny, 8), tiny);
+    }
+}
@@ -0,0 +1,264 @@
+use std::cell::{RefCell, RefMut};
+
+use smithay::{
+    desktop::Window,
+    output::Output,
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::State, wayland_server::Resource,
+    },
+    utils::{Logical, Point, Rectangle, Serial, Size},
+    wayland::{
+        compositor::with_states,
+        shell::xdg::{ToplevelSurface, XdgToplevelSurfaceData},
+    },
+};
+use tracing::error;
+
+use super::{rules::RuleSettings, ThingState};
+
+/// State of a window kept by the compositor, see [`window_state`]
+#[derive(Debug, Default)]
+pub struct WindowState {
+    pub maximized: bool,
+    pub fullscreen: bool,
+    /// Whether the window is left out of the tiling layout
+    pub floating: bool,
+    /// Settings given by the window rules
+    pub rules: RuleSettings,
+    /// Geometry of the window before it was maximized, made fullscreen or tiled on an edge,
+    /// restored afterwards
+    pub restore_geometry: Option<Rectangle<i32, Logical>>,
+    /// Whether the window was dropped on an edge of an output and took part of it, until it gets
+    /// moved again
+    pub edge_tiled: bool,
+}
+
+/// State of `window`, created the first time it is accessed
+pub fn window_state(window: &Window) -> RefMut<'_, WindowState> {
+    let user_data = window.user_data();
+    user_data.insert_if_missing(|| RefCell::new(WindowState::default()));
+    user_data
+        .get::<RefCell<WindowState>>()
+        .unwrap()
+        .borrow_mut()
+}
+
+impl ThingState {
+    /// Resizes `window` to the usable area of its output
+    pub fn maximize_window(&mut self, window: &Window) {
+        let Some(area) = self
+            .window_output(window)
+            .and_then(|output| self.usable_area(&output))
+        else {
+            return;
+        };
+
+        self.save_geometry(window);
+        window_state(window).maximized = true;
+        if let Some(toplevel) = window.toplevel() {
+            toplevel.with_pending_state(|state| state.states.set(State::Maximized));
+        } else if let Some(surface) = window.x11_surface() {
+            if let Err(err) = surface.set_maximized(true) {
+                error!(?err, "Unable to maximize X11 window");
+            }
+        }
+
+        // Unless it is fullscreen, in which case it gets maximized when leaving fullscreen
+        if !window_state(window).fullscreen {
+            self.configure_window(window, area.loc, Some(area.size));
+        } else if let Some(toplevel) = window.toplevel() {
+            send_configure(toplevel);
+        }
+        // The other windows take the place it had in the layout
+        self.retile_window_output(window);
+    }
+
+    /// Gives `window` back the geometry it had before being maximized
+    pub fn unmaximize_window(&mut self, window: &Window) {
+        window_state(window).maximized = false;
+        if let Some(toplevel) = window.toplevel() {
+            toplevel.with_pending_state(|state| state.states.unset(State::Maximized));
+        } else if let Some(surface) = window.x11_surface() {
+            if let Err(err) = surface.set_maximized(false) {
+                error!(?err, "Unable to unmaximize X11 window");
+            }
+        }
+
+        if !window_state(window).fullscreen {
+            self.restore_geometry(window);
+        } else if let Some(toplevel) = window.toplevel() {
+            send_configure(toplevel);
+        }
+        self.retile_window_output(window);
+    }
+
+    /// Makes `window` cover `output`, or its current output if `None`, above the other windows
+    pub fn fullscreen_window(&mut self, window: &Window, output: Option<Output>) {
+        let Some(output) = output.or_else(|| self.window_output(window)) else {
+            return;
+        };
+        let Some(area) = self.space.output_geometry(&output) else {
+            return;
+        };
+
+        self.save_geometry(window);
+        window_state(window).fullscreen = true;
+        if let Some(toplevel) = window.toplevel() {
+            let client = toplevel.wl_surface().client();
+            toplevel.with_pending_state(|state| {
+                state.states.set(State::Fullscreen);
+                state.fullscreen_output =
+                    client.and_then(|client| output.client_outputs(&client).into_iter().next());
+            });
+        } else if let Some(surface) = window.x11_surface() {
+            if let Err(err) = surface.set_fullscreen(true) {
+                error!(?err, "Unable to make X11 window fullscreen");
+            }
+        }
+
+        self.configure_window(window, area.loc, Some(area.size));
+        self.space.raise_element(window, false);
+        self.retile(&output);
+    }
+
+    /// Gives `window` back the geometry it had before being made fullscreen, or maximizes it again
+    pub fn unfullscreen_window(&mut self, window: &Window) {
+        window_state(window).fullscreen = false;
+        if let Some(toplevel) = window.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.unset(State::Fullscreen);
+                state.fullscreen_output = None;
+            });
+        } else if let Some(surface) = window.x11_surface() {
+            if let Err(err) = surface.set_fullscreen(false) {
+                error!(?err, "Unable to leave fullscreen for X11 window");
+            }
+        }
+
+        if window_state(window).maximized {
+            self.maximize_window(window);
+        } else {
+            self.restore_geometry(window);
+            self.retile_window_output(window);
+        }
+    }
+
+    pub fn toggle_maximized(&mut self, window: &Window) {
+        if window_state(window).maximized {
+            self.unmaximize_window(window);
+        } else {
+            self.maximize_window(window);
+        }
+    }
+
+    pub fn toggle_fullscreen(&mut self, window: &Window) {
+        if window_state(window).fullscreen {
+            self.unfullscreen_window(window);
+        } else {
+            self.fullscreen_window(window, None);
+        }
+    }
+
+    /// Hides `window`, until it is restored with [`Self::restore_window`]
+    pub fn minimize_window(&mut self, window: &Window) {
+        let Some(loc) = self.space.element_location(window) else {
+            return;
+        };
+        self.unmap_window(window);
+        self.minimized_windows.push((window.clone(), loc));
+    }
+
+    /// Shows and focuses the window minimized last
+    pub fn restore_window(&mut self, serial: Serial) {
+        let Some((window, loc)) = self.minimized_windows.pop() else {
+            return;
+        };
+        self.space.map_element(window.clone(), loc, false);
+        self.retile_window_output(&window);
+        self.focus_window(&window, serial);
+    }
+
+    /// The output `window` is mostly displayed on, or the one under the pointer if it is not
+    /// visible
+    fn window_output(&self, window: &Window) -> Option<Output> {
+        self.space
+            .outputs_for_element(window)
+            .into_iter()
+            .next()
+            .or_else(|| self.pointer_output())
+    }
+
+    /// Area of `output` available to windows
+    pub fn usable_area(&self, output: &Output) -> Option<Rectangle<i32, Logical>> {
+        // Nothing reserves space on outputs yet
+        self.space.output_geometry(output)
+    }
+
+    /// Remembers the geometry of `window`, unless it is already maximized or fullscreen
+    pub fn save_geometry(&mut self, window: &Window) {
+        let geometry = self
+            .space
+            .element_geometry(window)
+            .filter(|geo| !geo.size.is_empty());
+
+        let mut state = window_state(window);
+        if !state.maximized && !state.fullscreen {
+            state.restore_geometry = geometry;
+        }
+    }
+
+    /// Gives `window` the geometry saved by [`Self::save_geometry`], the client choosing its size
+    /// if there is none
+    fn restore_geometry(&mut self, window: &Window) {
+        let restore_geometry = window_state(window).restore_geometry.take();
+        match restore_geometry {
+            Some(geometry) => self.configure_window(window, geometry.loc, Some(geometry.size)),
+            None => {
+                let loc = self
+                    .space
+                    .element_geometry(window)
+                    .map(|geo| geo.loc)
+                    .unwrap_or_default();
+                self.configure_window(window, loc, None);
+            }
+        }
+    }
+
+    /// Moves the geometry of `window` to `loc`, and asks it to take the size `size`
+    pub fn configure_window(
+        &mut self,
+        window: &Window,
+        loc: Point<i32, Logical>,
+        size: Option<Size<i32, Logical>>,
+    ) {
+        self.queue_redraw_for_window(window);
+        self.space.map_element(window.clone(), loc, false);
+
+        if let Some(toplevel) = window.toplevel() {
+            toplevel.with_pending_state(|state| state.size = size);
+            send_configure(toplevel);
+        } else if let Some(surface) = window.x11_surface() {
+            let size = size.unwrap_or_else(|| surface.geometry().size);
+            if let Err(err) = surface.configure(Rectangle::from_loc_and_size(loc, size)) {
+                error!(?err, "Unable to configure X11 window");
+            }
+        }
+        self.queue_redraw_for_window(window);
+    }
+}
+
+/// Sends the pending state of `toplevel`, unless it did not get its initial configure yet, in
+/// which case the state is sent with it
+pub fn send_configure(toplevel: &ToplevelSurface) {
+    let initial_configure_sent = with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .and_then(|data| data.lock().ok().map(|data| data.initial_configure_sent))
+    })
+    .unwrap_or(false);
+
+    if initial_configure_sent {
+        toplevel.send_pending_configure();
+    }
+}
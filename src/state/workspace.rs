@@ -0,0 +1,269 @@
+use std::{fmt::Write as _, fs};
+
+use smithay::{
+    desktop::Window,
+    output::Output,
+    utils::{Logical, Point},
+};
+use tracing::{error, warn};
+
+use super::{window::send_configure, ThingState};
+
+/// Environment variable set for spawned commands, with the path of the file describing the
+/// workspaces.
+///
+/// The file is a supported interface for panels and scripts: it lives in `$XDG_RUNTIME_DIR`, is
+/// named after the wayland socket with a `.workspaces` extension, is replaced at once whenever it
+/// changes, so that it can be watched with inotify, and is removed when the compositor exits. Its
+/// format is described in [`ThingState::workspace_status`].
+pub const WORKSPACES_ENV: &str = "THING_WORKSPACES";
+
+/// Workspaces of an output, see [`ThingState::workspaces`].
+///
+/// The windows of the active workspace are the ones in the space, the others are kept here.
+#[derive(Debug, Default)]
+pub struct Workspaces {
+    /// Index of the displayed workspace, starting from 0
+    pub active: usize,
+    /// Windows of the other workspaces, with the index of their workspace and their location
+    pub hidden: Vec<(usize, Window, Point<i32, Logical>)>,
+}
+
+impl ThingState {
+    /// Switches to the workspace `number`, starting from 1, on the output of the focused window or
+    /// the one under the pointer
+    pub fn switch_workspace(&mut self, number: usize) {
+        let Some(index) = self.workspace_index(number) else {
+            return;
+        };
+        let Some(output) = self.active_output() else {
+            return;
+        };
+        self.show_workspace(&output, index);
+    }
+
+    /// Moves `window` to the workspace `number`, starting from 1, of the output it is on
+    pub fn move_to_workspace(&mut self, window: &Window, number: usize) {
+        let Some(index) = self.workspace_index(number) else {
+            return;
+        };
+        let Some(output) = self.space.outputs_for_element(window).into_iter().next() else {
+            return;
+        };
+        let Some(loc) = self.space.element_location(window) else {
+            return;
+        };
+        if self.output_workspaces(&output).active == index {
+            return;
+        }
+
+        self.unmap_window(window);
+        self.output_workspaces(&output)
+            .hidden
+            .push((index, window.clone(), loc));
+        self.workspaces_changed();
+    }
+
+    /// Windows which are not in the space, because they are on another workspace or minimized
+    pub fn hidden_windows(&self) -> Vec<Window> {
+        let mut windows: Vec<Window> = self
+            .minimized_windows
+            .iter()
+            .map(|(w, _)| w.clone())
+            .collect();
+        for workspaces in self.workspaces.values() {
+            windows.extend(workspaces.hidden.iter().map(|(_, w, _)| w.clone()));
+        }
+        windows
+    }
+
+    /// Forgets about `window` on the other workspaces, once it has been destroyed
+    pub fn remove_hidden_window(&mut self, window: &Window) {
+        for workspaces in self.workspaces.values_mut() {
+            workspaces.hidden.retain(|(_, w, _)| w != window);
+        }
+    }
+
+    /// Moves the windows of the workspaces which do not exist anymore to the last one, once the
+    /// configured number of workspaces changed
+    pub fn apply_workspace_count(&mut self) {
+        let last = self.config.workspaces - 1;
+        for workspaces in self.workspaces.values_mut() {
+            for (index, _, _) in &mut workspaces.hidden {
+                *index = (*index).min(last);
+            }
+        }
+
+        let outputs: Vec<Output> = self.space.outputs().cloned().collect();
+        for output in &outputs {
+            let workspaces = self.output_workspaces(output);
+            if workspaces.active <= last {
+                continue;
+            }
+
+            // The displayed windows stay, joined by the ones of the last workspace
+            workspaces.active = last;
+            for (window, loc) in self.take_workspace(output, last) {
+                self.space.map_element(window, loc, false);
+            }
+            self.retile(output);
+            self.render_scheduler.queue_redraw(output);
+        }
+        self.workspaces_changed();
+    }
+
+    /// Describes the workspaces of each output, one line per output.
+    ///
+    /// Lines are made of the name of the output, the number of its active workspace, and how many
+    /// windows each of its workspaces has, e.g. `HDMI-A-1 active=2 windows=3,1,0,0`. Workspaces are
+    /// numbered from 1, and the outputs are listed from left to right.
+    ///
+    /// This is the format of the file given by [`WORKSPACES_ENV`], which is kept stable: new
+    /// information is only ever added as `key=value` fields at the end of the lines, so readers
+    /// should ignore the fields they do not know.
+    pub fn workspace_status(&self) -> String {
+        let none = Workspaces::default();
+        let mut status = String::new();
+        for output in self.space.outputs() {
+            let workspaces = self.workspaces.get(&output.name()).unwrap_or(&none);
+            let mut windows = vec![0; self.config.workspaces.max(workspaces.active + 1)];
+            windows[workspaces.active] = self.workspace_windows(output).len();
+            for (index, _, _) in &workspaces.hidden {
+                if let Some(count) = windows.get_mut(*index) {
+                    *count += 1;
+                }
+            }
+
+            let windows: Vec<String> = windows.iter().map(ToString::to_string).collect();
+            let _ = writeln!(
+                status,
+                "{} active={} windows={}",
+                output.name(),
+                workspaces.active + 1,
+                windows.join(","),
+            );
+        }
+        status
+    }
+
+    /// Writes [`Self::workspace_status`] to its file, for panels to display it.
+    ///
+    /// Should be called whenever a workspace gets switched to, or gains or loses a window.
+    pub fn workspaces_changed(&self) {
+        let Some(path) = &self.workspaces_path else {
+            return;
+        };
+        // Replaced at once, so that readers never see a partly written file
+        let tmp_path = path.with_extension("tmp");
+        let res =
+            fs::write(&tmp_path, self.workspace_status()).and_then(|_| fs::rename(&tmp_path, path));
+        if let Err(err) = res {
+            error!(?err, "Unable to write the workspaces to {}", path.display());
+        }
+    }
+
+    /// Workspaces of `output`, created the first time they are accessed
+    fn output_workspaces(&mut self, output: &Output) -> &mut Workspaces {
+        self.workspaces.entry(output.name()).or_default()
+    }
+
+    /// Hides the windows of the active workspace of `output` and shows the ones of the workspace
+    /// `index`, focusing the window focused last there
+    fn show_workspace(&mut self, output: &Output, index: usize) {
+        let active = self.output_workspaces(output).active;
+        if active == index {
+            return;
+        }
+
+        // Windows without a buffer yet are placed on the active workspace later
+        let windows: Vec<Window> = self
+            .workspace_windows(output)
+            .into_iter()
+            .filter(|w| !self.unplaced_windows.contains(w))
+            .collect();
+        for window in windows {
+            let Some(loc) = self.space.element_location(&window) else {
+                continue;
+            };
+            self.queue_redraw_for_window(&window);
+            self.space.unmap_elem(&window);
+            if window.set_activated(false) {
+                if let Some(toplevel) = window.toplevel() {
+                    send_configure(toplevel);
+                }
+            }
+            self.output_workspaces(output)
+                .hidden
+                .push((active, window, loc));
+        }
+
+        self.output_workspaces(output).active = index;
+        let shown = self.take_workspace(output, index);
+        // In the order they were hidden, which keeps their stacking order
+        for (window, loc) in shown {
+            self.space.map_element(window.clone(), loc, false);
+            self.queue_redraw_for_window(&window);
+        }
+
+        self.retile(output);
+        self.focus_fallback(Some(output));
+        self.render_scheduler.queue_redraw(output);
+        self.workspaces_changed();
+    }
+
+    /// Removes the windows of the workspace `index` of `output` from the hidden ones, with their
+    /// location
+    fn take_workspace(
+        &mut self,
+        output: &Output,
+        index: usize,
+    ) -> Vec<(Window, Point<i32, Logical>)> {
+        let workspaces = self.output_workspaces(output);
+        let (shown, hidden): (Vec<_>, Vec<_>) = std::mem::take(&mut workspaces.hidden)
+            .into_iter()
+            .partition(|(i, _, _)| *i == index);
+        workspaces.hidden = hidden;
+        shown.into_iter().map(|(_, w, loc)| (w, loc)).collect()
+    }
+
+    /// Windows of the active workspace of `output`, the ones mostly displayed on it.
+    ///
+    /// Menus and tooltips of X11 clients are left out, they belong to their parent window rather
+    /// than to a workspace.
+    fn workspace_windows(&self, output: &Output) -> Vec<Window> {
+        self.space
+            .elements()
+            .filter(|w| !w.x11_surface().is_some_and(|s| s.is_override_redirect()))
+            .filter(|w| self.space.outputs_for_element(w).first() == Some(output))
+            .cloned()
+            .collect()
+    }
+
+    /// The output of the focused window, or the one under the pointer if nothing is focused
+    fn active_output(&self) -> Option<Output> {
+        self.focused_window()
+            .and_then(|window| self.space.outputs_for_element(&window).into_iter().next())
+            .or_else(|| self.pointer_output())
+    }
+
+    /// Index of the workspace `number`, if it exists
+    fn workspace_index(&self, number: usize) -> Option<usize> {
+        if number == 0 || number > self.config.workspaces {
+            warn!(
+                "There is no workspace {}, the workspaces go from 1 to {}",
+                number, self.config.workspaces
+            );
+            return None;
+        }
+        Some(number - 1)
+    }
+}
+
+impl Drop for ThingState {
+    /// Removes the file describing the workspaces, however the compositor exits
+    fn drop(&mut self) {
+        if let Some(path) = &self.workspaces_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
@@ -10,10 +10,11 @@ use smithay::{
         pointer::{Focus, GrabStartData},
         Seat,
     },
+    output::Output,
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge,
         wayland_server::{
-            protocol::{wl_seat::WlSeat, wl_surface::WlSurface},
+            protocol::{wl_output::WlOutput, wl_seat::WlSeat, wl_surface::WlSurface},
             Resource,
         },
     },
@@ -22,12 +23,14 @@ use smithay::{
         compositor::with_states,
         seat::WaylandFocus,
         shell::xdg::{
-            Configure, PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState, XdgToplevelSurfaceData
+            Configure, PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler,
+            XdgShellState, XdgToplevelSurfaceData,
         },
     },
 };
 use tracing::{error, trace};
 
+pub mod key_grab;
 pub mod move_grab;
 pub mod resize_grab;
 
@@ -60,6 +63,21 @@ impl ThingState {
             state.geometry = state.positioner.get_unconstrained_geometry(output_geo);
         });
     }
+
+    /// The window of `surface`, if it is displayed
+    fn displayed_window(&self, surface: &ToplevelSurface) -> Option<Window> {
+        self.window_for_surface(surface.wl_surface().clone())
+            .cloned()
+    }
+
+    /// The window of `surface`, displayed or not
+    fn toplevel_window(&self, surface: &ToplevelSurface) -> Option<Window> {
+        self.space
+            .elements()
+            .cloned()
+            .chain(self.hidden_windows())
+            .find(|w| w.toplevel() == Some(surface))
+    }
 }
 
 impl XdgShellHandler for ThingState {
@@ -70,7 +88,30 @@ impl XdgShellHandler for ThingState {
     fn new_toplevel(&mut self, surface: ToplevelSurface) {
         trace!(?surface, "new top level");
         let window = Window::new_wayland_window(surface);
-        self.space.map_element(window, (0, 0), true);
+        // Placed on the first commit with a buffer, see `ThingState::place_if_sized`
+        self.space.map_element(window.clone(), (0, 0), false);
+        self.unplaced_windows.push(window.clone());
+        self.apply_window_rules(&window);
+    }
+
+    fn title_changed(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.toplevel_window(&surface) {
+            self.apply_window_rules(&window);
+        }
+    }
+
+    fn app_id_changed(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.toplevel_window(&surface) {
+            self.apply_window_rules(&window);
+        }
+    }
+
+    fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
+        let window = self.toplevel_window(&surface);
+        if let Some(window) = window {
+            self.remove_window(&window);
+        }
+        self.queue_redraw_all();
     }
 
     fn new_popup(&mut self, surface: PopupSurface, positioner: PositionerState) {
@@ -85,6 +126,10 @@ impl XdgShellHandler for ThingState {
         }
     }
 
+    fn popup_destroyed(&mut self, _surface: PopupSurface) {
+        self.queue_redraw_all();
+    }
+
     fn grab(&mut self, surface: PopupSurface, seat: WlSeat, serial: Serial) {
         trace!(?surface, "new popup grab");
         let Some(seat) = Seat::<ThingState>::from_resource(&seat) else {
@@ -165,6 +210,44 @@ impl XdgShellHandler for ThingState {
         resize_grab::handle_resize_request(self, window.clone(), seat, serial, edges.into());
     }
 
+    fn maximize_request(&mut self, surface: ToplevelSurface) {
+        match self.displayed_window(&surface) {
+            Some(window) => self.maximize_window(&window),
+            // Always reply with a configure, even when the request cannot be honoured
+            None => surface.send_configure(),
+        }
+    }
+
+    fn unmaximize_request(&mut self, surface: ToplevelSurface) {
+        match self.displayed_window(&surface) {
+            Some(window) => self.unmaximize_window(&window),
+            None => surface.send_configure(),
+        }
+    }
+
+    fn fullscreen_request(&mut self, surface: ToplevelSurface, output: Option<WlOutput>) {
+        match self.displayed_window(&surface) {
+            Some(window) => {
+                let output = output.as_ref().and_then(Output::from_resource);
+                self.fullscreen_window(&window, output);
+            }
+            None => surface.send_configure(),
+        }
+    }
+
+    fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
+        match self.displayed_window(&surface) {
+            Some(window) => self.unfullscreen_window(&window),
+            None => surface.send_configure(),
+        }
+    }
+
+    fn minimize_request(&mut self, surface: ToplevelSurface) {
+        if let Some(window) = self.displayed_window(&surface) {
+            self.minimize_window(&window);
+        }
+    }
+
     fn reposition_request(
         &mut self,
         surface: PopupSurface,
@@ -0,0 +1,225 @@
+use smithay::{
+    backend::input::KeyState,
+    desktop::Window,
+    input::keyboard::{GrabStartData, KeyboardGrab, KeyboardInnerHandle, Keycode, ModifiersState},
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel::State,
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::{Logical, Point, Rectangle, Serial, Size},
+};
+use tracing::error;
+
+use crate::state::{
+    keycodes::{KEY_DOWN, KEY_ENTER, KEY_ESCAPE, KEY_KP_ENTER, KEY_LEFT, KEY_RIGHT, KEY_UP},
+    window::send_configure,
+    ThingState,
+};
+
+use super::resize_grab::{begin_resize, cancel_resize, clamp_size, end_resize, ResizeEdge};
+
+/// Distance a window is moved or resized by for each key press
+const STEP: i32 = 10;
+/// Distance used instead of [`STEP`] while Shift is held
+const BIG_STEP: i32 = 100;
+
+/// Moves or resizes a window with the arrow keys, until Enter keeps the new geometry or Escape
+/// gives it back its initial one.
+///
+/// When resizing, the arrows move the bottom-right corner of the window, and the top-left corner
+/// while Ctrl is held.
+pub struct MoveResizeKeyboardGrab {
+    pub start_data: GrabStartData<ThingState>,
+    pub window: Window,
+    /// Location of the window in the space and its size before the grab
+    pub initial_rect: Rectangle<i32, Logical>,
+    pub resize: bool,
+    /// Geometry the window was last asked to take
+    rect: Rectangle<i32, Logical>,
+    /// Edges the window was last resized from, with its geometry before that, which its location
+    /// follows until the end of the grab
+    last_resize: Option<(ResizeEdge, Rectangle<i32, Logical>)>,
+    modifiers: ModifiersState,
+}
+
+impl MoveResizeKeyboardGrab {
+    pub fn start(
+        start_data: GrabStartData<ThingState>,
+        window: Window,
+        initial_rect: Rectangle<i32, Logical>,
+        resize: bool,
+        modifiers: ModifiersState,
+    ) -> Self {
+        if resize {
+            if let Some(toplevel) = window.toplevel() {
+                toplevel.with_pending_state(|state| {
+                    state.states.set(State::Resizing);
+                });
+                send_configure(toplevel);
+            }
+        }
+
+        Self {
+            start_data,
+            window,
+            initial_rect,
+            resize,
+            rect: initial_rect,
+            last_resize: None,
+            modifiers,
+        }
+    }
+
+    /// Moves the window by `delta`
+    fn move_by(&mut self, data: &mut ThingState, delta: Point<i32, Logical>) {
+        self.rect.loc += delta;
+        data.queue_redraw_for_window(&self.window);
+        data.space
+            .map_element(self.window.clone(), self.rect.loc, false);
+        if let Some(surface) = self.window.x11_surface() {
+            if let Err(err) = surface.configure(self.rect) {
+                error!(?err, "Unable to configure X11 window");
+            }
+        }
+        data.queue_redraw_for_window(&self.window);
+    }
+
+    /// Moves the bottom and right edges of the window by `delta`, or the top and left ones if
+    /// `top_left` is set
+    fn resize_by(&mut self, data: &mut ThingState, delta: Point<i32, Logical>, top_left: bool) {
+        let (edges, grow) = match (top_left, delta.x != 0) {
+            (false, true) => (ResizeEdge::RIGHT, Size::from((delta.x, 0))),
+            (false, false) => (ResizeEdge::BOTTOM, Size::from((0, delta.y))),
+            (true, true) => (ResizeEdge::LEFT, Size::from((-delta.x, 0))),
+            (true, false) => (ResizeEdge::TOP, Size::from((0, -delta.y))),
+        };
+
+        // Successive presses add up, even when the window did not commit the previous size yet
+        let current = self.rect;
+        let size = clamp_size(&self.window, current.size + grow);
+
+        let mut rect = Rectangle::from_loc_and_size(current.loc, size);
+        if edges.intersects(ResizeEdge::LEFT) {
+            rect.loc.x += current.size.w - size.w;
+        }
+        if edges.intersects(ResizeEdge::TOP) {
+            rect.loc.y += current.size.h - size.h;
+        }
+        self.rect = rect;
+
+        if let Some(toplevel) = self.window.toplevel() {
+            // The location is adjusted once the window commits its new size
+            begin_resize(&self.window, edges, current);
+            self.last_resize = Some((edges, current));
+            toplevel.with_pending_state(|state| state.size = Some(size));
+            send_configure(toplevel);
+        } else if let Some(surface) = self.window.x11_surface() {
+            data.space.map_element(self.window.clone(), rect.loc, false);
+            if let Err(err) = surface.configure(rect) {
+                error!(?err, "Unable to configure X11 window");
+            }
+            data.queue_redraw_for_window(&self.window);
+        }
+    }
+
+    /// Gives the window back its geometry from before the grab
+    fn revert(&mut self, data: &mut ThingState) {
+        data.queue_redraw_for_window(&self.window);
+        data.space
+            .map_element(self.window.clone(), self.initial_rect.loc, false);
+        if let Some(toplevel) = self.window.toplevel() {
+            if self.resize {
+                cancel_resize(&self.window);
+                self.last_resize = None;
+                toplevel.with_pending_state(|state| state.size = Some(self.initial_rect.size));
+            }
+        } else if let Some(surface) = self.window.x11_surface() {
+            if let Err(err) = surface.configure(self.initial_rect) {
+                error!(?err, "Unable to configure X11 window");
+            }
+        }
+        self.rect = self.initial_rect;
+        data.queue_redraw_for_window(&self.window);
+    }
+}
+
+impl KeyboardGrab<ThingState> for MoveResizeKeyboardGrab {
+    fn input(
+        &mut self,
+        data: &mut ThingState,
+        handle: &mut KeyboardInnerHandle<'_, ThingState>,
+        keycode: Keycode,
+        state: KeyState,
+        modifiers: Option<ModifiersState>,
+        serial: Serial,
+        time: u32,
+    ) {
+        // Only modifiers are sent to clients, so they know when they are released
+        if let Some(modifiers) = modifiers {
+            self.modifiers = modifiers;
+            handle.input(data, keycode, state, Some(modifiers), serial, time);
+        }
+        if state != KeyState::Pressed {
+            return;
+        }
+        // The window may have been closed meanwhile
+        if data.space.element_location(&self.window).is_none() {
+            handle.unset_grab(self, data, serial, false);
+            return;
+        }
+
+        let step = if self.modifiers.shift { BIG_STEP } else { STEP };
+        let delta: Point<i32, Logical> = match keycode.raw() {
+            KEY_UP => (0, -step).into(),
+            KEY_DOWN => (0, step).into(),
+            KEY_LEFT => (-step, 0).into(),
+            KEY_RIGHT => (step, 0).into(),
+            KEY_ENTER | KEY_KP_ENTER => {
+                handle.unset_grab(self, data, serial, false);
+                return;
+            }
+            KEY_ESCAPE => {
+                self.revert(data);
+                handle.unset_grab(self, data, serial, false);
+                return;
+            }
+            _ => return,
+        };
+
+        if self.resize {
+            self.resize_by(data, delta, self.modifiers.ctrl);
+        } else {
+            self.move_by(data, delta);
+        }
+    }
+
+    fn set_focus(
+        &mut self,
+        data: &mut ThingState,
+        handle: &mut KeyboardInnerHandle<'_, ThingState>,
+        focus: Option<WlSurface>,
+        serial: Serial,
+    ) {
+        handle.set_focus(data, focus, serial);
+    }
+
+    fn start_data(&self) -> &GrabStartData<ThingState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, data: &mut ThingState) {
+        if !self.resize {
+            return;
+        }
+        if let Some(toplevel) = self.window.toplevel() {
+            if let Some((edges, rect)) = self.last_resize {
+                end_resize(&self.window, edges, rect);
+            }
+            toplevel.with_pending_state(|state| {
+                state.states.unset(State::Resizing);
+            });
+            send_configure(toplevel);
+        }
+        data.queue_redraw_for_window(&self.window);
+    }
+}
@@ -31,9 +31,13 @@ impl PointerGrab<ThingState> for MovePointerGrab {
         handle.motion(data, None, event);
 
         let delta = event.location - self.start_data.location;
-        let new_location = self.initial_window_location.to_f64() + delta;
+        let new_location = (self.initial_window_location.to_f64() + delta).to_i32_round();
+        let new_location = data.snap_location(&self.window, new_location);
         data.space
-            .map_element(self.window.clone(), new_location.to_i32_round(), true);
+            .map_element(self.window.clone(), new_location, false);
+
+        let tile_area = data.edge_tile_area(event.location);
+        data.set_snap_preview(tile_area);
     }
 
     fn relative_motion(
@@ -54,18 +58,13 @@ impl PointerGrab<ThingState> for MovePointerGrab {
     ) {
         handle.button(data, event);
 
-        // =====
-        // TEMPORARY LOGIC
-        //
-        // This will be moved and made configurable in the future.
-        // For now, it's just to have a minimal way to changing focus, moving windows, etc.
-        // =====
-
-        const BTN_LEFT: u32 = 0x110;
-
-        if !handle.current_pressed().contains(&BTN_LEFT) {
-            // No more buttons are pressed, release the grab.
+        if !handle.current_pressed().contains(&self.start_data.button) {
+            // The button which started the grab was released
+            let tile_area = data.snap_preview.as_ref().map(|p| p.rect);
             handle.unset_grab(self, data, event.serial, event.time, true);
+            if let Some(area) = tile_area {
+                data.tile_on_edge(&self.window, area);
+            }
         }
     }
 
@@ -158,7 +157,9 @@ impl PointerGrab<ThingState> for MovePointerGrab {
         handle.gesture_hold_end(data, event);
     }
 
-    fn unset(&mut self, _data: &mut ThingState) {}
+    fn unset(&mut self, data: &mut ThingState) {
+        data.set_snap_preview(None);
+    }
 }
 
 pub fn handle_move_request(
@@ -176,6 +177,9 @@ pub fn handle_move_request(
 
     let pointer = seat.get_pointer().unwrap();
 
+    // Windows moved by hand leave the layout
+    state.float_window(&window);
+    state.untile_from_edge(&window, Some(pointer.current_location()));
     let initial_window_location = state.space.element_location(&window).unwrap();
 
     let grab = MovePointerGrab {
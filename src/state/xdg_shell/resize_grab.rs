@@ -40,6 +40,44 @@ bitflags::bitflags! {
     }
 }
 
+impl ResizeEdge {
+    /// Edges to resize when grabbing a window of size `size` at `point`, relative to its
+    /// geometry.
+    ///
+    /// The window is split in ninths, the ones on the sides and corners resize the matching edges,
+    /// and the centre one resizes the nearest corner.
+    pub fn from_position(size: Size<i32, Logical>, point: Point<f64, Logical>) -> Self {
+        let third_w = size.w as f64 / 3.0;
+        let third_h = size.h as f64 / 3.0;
+
+        let mut edges = ResizeEdge::empty();
+        if point.x < third_w {
+            edges |= ResizeEdge::LEFT;
+        } else if point.x >= third_w * 2.0 {
+            edges |= ResizeEdge::RIGHT;
+        }
+        if point.y < third_h {
+            edges |= ResizeEdge::TOP;
+        } else if point.y >= third_h * 2.0 {
+            edges |= ResizeEdge::BOTTOM;
+        }
+
+        if edges.is_empty() {
+            edges |= if point.x < size.w as f64 / 2.0 {
+                ResizeEdge::LEFT
+            } else {
+                ResizeEdge::RIGHT
+            };
+            edges |= if point.y < size.h as f64 / 2.0 {
+                ResizeEdge::TOP
+            } else {
+                ResizeEdge::BOTTOM
+            };
+        }
+        edges
+    }
+}
+
 impl From<xdg_toplevel::ResizeEdge> for ResizeEdge {
     #[inline]
     fn from(x: xdg_toplevel::ResizeEdge) -> Self {
@@ -72,15 +110,7 @@ impl ResizePointerGrab {
         edges: ResizeEdge,
     ) -> Self {
         let last_window_size = initial_rect.size;
-
-        if let Some(surface) = window.wl_surface().map(|s| s.into_owned()) {
-            ResizeSurfaceState::with(&surface, |state| {
-                *state = ResizeSurfaceState::Resizing {
-                    edges,
-                    initial_rect,
-                };
-            });
-        }
+        begin_resize(&window, edges, initial_rect);
 
         Self {
             start_data,
@@ -118,31 +148,13 @@ impl PointerGrab<ThingState> for ResizePointerGrab {
             delta.y = 0.0;
         }
 
-        let Some(surface) = self.window.wl_surface().map(|s| s.into_owned()) else {
+        if self.window.wl_surface().is_none() {
             error!("Can't get surface for resize grab");
             return;
-        };
-
-        let (min_size, max_size) = compositor::with_states(&surface, |states| {
-            let mut guard = states.cached_state.get::<SurfaceCachedState>();
-            let data = guard.current();
-            (data.min_size, data.max_size)
-        });
-
-        let min_width = min_size.w.max(1);
-        let min_height = min_size.h.max(1);
-
-        let max_width = (max_size.w == 0).then(i32::max_value).unwrap_or(max_size.w);
-        let max_height = (max_size.h == 0).then(i32::max_value).unwrap_or(max_size.h);
+        }
 
-        self.last_window_size = Size::from((
-            (self.initial_rect.size.w + delta.x as i32)
-                .min(max_width)
-                .max(min_width),
-            (self.initial_rect.size.h + delta.y as i32)
-                .min(max_height)
-                .max(min_height),
-        ));
+        let size = self.initial_rect.size + Size::from((delta.x as i32, delta.y as i32));
+        self.last_window_size = clamp_size(&self.window, size);
 
         if let Some(toplevel) = self.window.toplevel() {
             toplevel.with_pending_state(|state| {
@@ -171,20 +183,10 @@ impl PointerGrab<ThingState> for ResizePointerGrab {
     ) {
         handle.button(data, event);
 
-        const BTN_RIGHT: u32 = 0x111;
-
-        if !handle.current_pressed().contains(&BTN_RIGHT) {
-            // No more buttons are pressed, release the grab.
+        if !handle.current_pressed().contains(&self.start_data.button) {
+            // The button which started the grab was released
             handle.unset_grab(self, data, event.serial, event.time, true);
-
-            if let Some(surface) = self.window.wl_surface().map(|s| s.into_owned()) {
-                ResizeSurfaceState::with(&surface, |state| {
-                    *state = ResizeSurfaceState::WaitingForLastCommit {
-                        edges: self.edges,
-                        initial_rect: self.initial_rect,
-                    };
-                });
-            }
+            end_resize(&self.window, self.edges, self.initial_rect);
 
             if let Some(xdg) = self.window.toplevel() {
                 xdg.with_pending_state(|state| {
@@ -347,6 +349,63 @@ impl ResizeSurfaceState {
     }
 }
 
+/// Makes the location of `window` follow the `edges` it is resized from, see [`handle_commit`].
+///
+/// `initial_rect` is the location of the window in the space and its size before the resize.
+pub fn begin_resize(window: &Window, edges: ResizeEdge, initial_rect: Rectangle<i32, Logical>) {
+    if let Some(surface) = window.wl_surface().map(|s| s.into_owned()) {
+        ResizeSurfaceState::with(&surface, |state| {
+            *state = ResizeSurfaceState::Resizing {
+                edges,
+                initial_rect,
+            };
+        });
+    }
+}
+
+/// Keeps the location of `window` following the resized edges until its next commit, once the
+/// resize is over
+pub fn end_resize(window: &Window, edges: ResizeEdge, initial_rect: Rectangle<i32, Logical>) {
+    if let Some(surface) = window.wl_surface().map(|s| s.into_owned()) {
+        ResizeSurfaceState::with(&surface, |state| {
+            *state = ResizeSurfaceState::WaitingForLastCommit {
+                edges,
+                initial_rect,
+            };
+        });
+    }
+}
+
+/// Stops moving `window` along with the edges it was resized from
+pub fn cancel_resize(window: &Window) {
+    if let Some(surface) = window.wl_surface().map(|s| s.into_owned()) {
+        ResizeSurfaceState::with(&surface, |state| *state = ResizeSurfaceState::Idle);
+    }
+}
+
+/// Restricts `size` to the minimum and maximum sizes of `window`
+pub fn clamp_size(window: &Window, size: Size<i32, Logical>) -> Size<i32, Logical> {
+    let (min_size, max_size) = match window.wl_surface() {
+        Some(surface) => compositor::with_states(&surface, |states| {
+            let mut guard = states.cached_state.get::<SurfaceCachedState>();
+            let data = guard.current();
+            (data.min_size, data.max_size)
+        }),
+        None => Default::default(),
+    };
+
+    let min_width = min_size.w.max(1);
+    let min_height = min_size.h.max(1);
+
+    let max_width = (max_size.w == 0).then(i32::max_value).unwrap_or(max_size.w);
+    let max_height = (max_size.h == 0).then(i32::max_value).unwrap_or(max_size.h);
+
+    Size::from((
+        size.w.min(max_width).max(min_width),
+        size.h.min(max_height).max(min_height),
+    ))
+}
+
 pub fn handle_resize_request(
     state: &mut ThingState,
     window: Window,
@@ -363,6 +422,9 @@ pub fn handle_resize_request(
 
     let pointer = seat.get_pointer().unwrap();
 
+    // Windows resized by hand leave the layout
+    state.float_window(&window);
+    state.untile_from_edge(&window, None);
     let initial_location = state.space.element_location(&window).unwrap();
     let initial_size = window.geometry().size;
 
@@ -3,11 +3,17 @@ use std::process::Stdio;
 use smithay::{
     delegate_xwayland_shell,
     desktop::{space::SpaceElement, Window},
-    reexports::{calloop::LoopHandle, wayland_server::DisplayHandle},
+    reexports::{
+        calloop::LoopHandle,
+        wayland_server::{protocol::wl_surface::WlSurface, DisplayHandle},
+    },
     utils::{Logical, Rectangle, SERIAL_COUNTER},
-    wayland::xwayland_shell::{XWaylandShellHandler, XWaylandShellState},
+    wayland::{
+        seat::WaylandFocus,
+        xwayland_shell::{XWaylandShellHandler, XWaylandShellState},
+    },
     xwayland::{
-        xwm::{Reorder, ResizeEdge, XwmId},
+        xwm::{Reorder, ResizeEdge, WmWindowProperty, XwmId},
         X11Surface, X11Wm, XWayland, XWaylandEvent, XwmHandler,
     },
 };
@@ -20,7 +26,8 @@ use super::{
     ThingState,
 };
 
-pub fn setup(dh: &DisplayHandle, loop_handle: LoopHandle<'static, CalloopData>) {
+/// Spawns XWayland, and returns the display number it will listen on
+pub fn setup(dh: &DisplayHandle, loop_handle: LoopHandle<'static, CalloopData>) -> Option<u32> {
     let Ok((xwayland, xw_client)) = XWayland::spawn::<&str, &str, _, _>(
         &dh,
         None,
@@ -30,8 +37,10 @@ pub fn setup(dh: &DisplayHandle, loop_handle: LoopHandle<'static, CalloopData>)
         std::io::stderr(),
         |_user_data| {},
     ) else {
-        return error!("Unable to spawn XWayland");
+        error!("Unable to spawn XWayland");
+        return None;
     };
+    let display_number = xwayland.display_number();
 
     let res = loop_handle.insert_source(xwayland, move |event, _, data| match event {
         XWaylandEvent::Ready {
@@ -42,18 +51,23 @@ pub fn setup(dh: &DisplayHandle, loop_handle: LoopHandle<'static, CalloopData>)
                 "XWayland server started successfully with display number {}",
                 display_number
             );
-            std::env::set_var("DISPLAY", format!(":{}", display_number.to_string()));
             match X11Wm::start_wm(
                 data.state.loop_handle.clone(),
                 x11_socket,
                 xw_client.clone(),
             ) {
                 Ok(xwm) => data.state.xwm = Some(xwm),
-                Err(err) => error!(?err, "Unable to start X11 Window Manager"),
+                Err(err) => {
+                    error!(?err, "Unable to start X11 Window Manager");
+                    data.state.xdisplay = None;
+                }
             }
+            data.state.run_startup_command();
         }
         XWaylandEvent::Error => {
             error!("XWayland exited unexpectedly on startup");
+            data.state.xdisplay = None;
+            data.state.run_startup_command();
         }
     });
 
@@ -62,7 +76,10 @@ pub fn setup(dh: &DisplayHandle, loop_handle: LoopHandle<'static, CalloopData>)
             ?err,
             "Error when inserting xwayland event source to the loop"
         );
+        return None;
     }
+
+    Some(display_number)
 }
 
 impl XwmHandler for CalloopData {
@@ -131,12 +148,36 @@ impl XwmHandler for CalloopData {
     fn move_request(&mut self, xwm: XwmId, surface: X11Surface, button: u32) {
         self.state.move_request(xwm, surface, button)
     }
+
+    fn maximize_request(&mut self, xwm: XwmId, surface: X11Surface) {
+        self.state.maximize_request(xwm, surface)
+    }
+
+    fn unmaximize_request(&mut self, xwm: XwmId, surface: X11Surface) {
+        self.state.unmaximize_request(xwm, surface)
+    }
+
+    fn fullscreen_request(&mut self, xwm: XwmId, surface: X11Surface) {
+        self.state.fullscreen_request(xwm, surface)
+    }
+
+    fn unfullscreen_request(&mut self, xwm: XwmId, surface: X11Surface) {
+        self.state.unfullscreen_request(xwm, surface)
+    }
+
+    fn property_notify(&mut self, xwm: XwmId, surface: X11Surface, property: WmWindowProperty) {
+        self.state.property_notify(xwm, surface, property)
+    }
 }
 
 impl XWaylandShellHandler for CalloopData {
     fn xwayland_shell_state(&mut self) -> &mut XWaylandShellState {
         &mut self.state.xw_shell_state
     }
+
+    fn surface_associated(&mut self, xwm: XwmId, wl_surface: WlSurface, surface: X11Surface) {
+        self.state.surface_associated(xwm, wl_surface, surface)
+    }
 }
 
 impl XwmHandler for ThingState {
@@ -144,44 +185,71 @@ impl XwmHandler for ThingState {
         self.xwm.as_mut().unwrap()
     }
 
-    fn new_window(&mut self, xwm: XwmId, surface: X11Surface) {
+    fn new_window(&mut self, _xwm: XwmId, surface: X11Surface) {
+        // Added to the space once it asks to be mapped
         let window = Window::new_x11_window(surface);
-        self.space.map_element(window, (0, 0), true);
+        self.apply_window_rules(&window);
+        self.unmapped_x11_windows.push(window);
     }
 
-    fn new_override_redirect_window(&mut self, xwm: XwmId, window: X11Surface) {
-        <Self as XwmHandler>::new_window(self, xwm, window);
-    }
+    fn new_override_redirect_window(&mut self, xwm: XwmId, window: X11Surface) {}
 
-    fn map_window_request(&mut self, xwm: XwmId, window: X11Surface) {
-        let window = self
-            .space
-            .elements()
-            .filter_map(|w| w.x11_surface())
-            .find(|&s| s == &window);
+    fn map_window_request(&mut self, xwm: XwmId, surface: X11Surface) {
+        if let Err(err) = surface.set_mapped(true) {
+            error!(?err, "Unable to map X11 window");
+            return;
+        }
 
-        if let Some(window) = window {
-            window.set_mapped(true);
-            window.set_activate(true);
+        let unmapped = self
+            .unmapped_x11_windows
+            .iter()
+            .position(|w| w.x11_surface() == Some(&surface));
+        let window = match unmapped {
+            Some(index) => self.unmapped_x11_windows.swap_remove(index),
+            // Mapped again after being unmapped
+            None => {
+                let window = Window::new_x11_window(surface);
+                self.apply_window_rules(&window);
+                window
+            }
+        };
+        self.space.map_element(window.clone(), (0, 0), false);
+        self.place_window(&window);
+        // Otherwise it gets focused once its wayland surface is known
+        if window.wl_surface().is_some() {
+            self.focus_window(&window, SERIAL_COUNTER.next_serial());
         }
     }
 
-    fn mapped_override_redirect_window(&mut self, xwm: XwmId, window: X11Surface) {}
+    fn mapped_override_redirect_window(&mut self, xwm: XwmId, surface: X11Surface) {
+        // Override-redirect windows (menus, tooltips...) choose their own location
+        let loc = surface.geometry().loc;
+        let window = Window::new_x11_window(surface);
+        self.space.map_element(window, loc, false);
+        self.queue_redraw_all();
+    }
 
-    fn unmapped_window(&mut self, xwm: XwmId, window: X11Surface) {
+    fn unmapped_window(&mut self, xwm: XwmId, surface: X11Surface) {
         let window = self
             .space
             .elements()
-            .filter_map(|w| w.x11_surface())
-            .find(|&s| s == &window);
+            .cloned()
+            .chain(self.hidden_windows())
+            .find(|w| w.x11_surface() == Some(&surface));
 
+        if !surface.is_override_redirect() {
+            surface.set_mapped(false);
+        }
+        // Mapping it again creates a new window
         if let Some(window) = window {
-            window.set_mapped(false);
-            window.set_activate(false);
+            self.remove_window(&window);
         }
+        self.queue_redraw_all();
     }
 
     fn destroyed_window(&mut self, xwm: XwmId, window: X11Surface) {
+        self.unmapped_x11_windows
+            .retain(|w| w.x11_surface() != Some(&window));
         <Self as XwmHandler>::unmapped_window(self, xwm, window);
     }
 
@@ -195,6 +263,18 @@ impl XwmHandler for ThingState {
         h: Option<u32>,
         _reorder: Option<Reorder>,
     ) {
+        // Tiled windows keep the geometry given by the layout
+        let tiled = self
+            .x11_window(&window)
+            .filter(|w| self.is_tiled(w))
+            .and_then(|w| self.space.element_geometry(&w));
+        if let Some(geometry) = tiled {
+            if let Err(err) = window.configure(geometry) {
+                error!(?err, "Unable to configure window");
+            }
+            return;
+        }
+
         let mut geometry = window.geometry();
         geometry.loc = (x.unwrap_or(geometry.loc.x), y.unwrap_or(geometry.loc.y)).into();
         geometry.size = (
@@ -210,6 +290,7 @@ impl XwmHandler for ThingState {
         if let Err(err) = window.configure(geometry) {
             error!(?err, "Unable to configure window");
         }
+        self.queue_redraw_all();
 
         // TODO: Restack
     }
@@ -231,10 +312,13 @@ impl XwmHandler for ThingState {
         _button: u32,
         resize_edge: ResizeEdge,
     ) {
+        let Some(window) = self.x11_window(&surface) else {
+            return;
+        };
         let seat = self.seat.clone();
         resize_grab::handle_resize_request(
             self,
-            Window::new_x11_window(surface),
+            window,
             seat,
             SERIAL_COUNTER.next_serial(),
             resize_edge.into(),
@@ -242,13 +326,73 @@ impl XwmHandler for ThingState {
     }
 
     fn move_request(&mut self, _xwm: XwmId, surface: X11Surface, _button: u32) {
+        let Some(window) = self.x11_window(&surface) else {
+            return;
+        };
         let seat = self.seat.clone();
-        move_grab::handle_move_request(
-            self,
-            Window::new_x11_window(surface),
-            seat,
-            SERIAL_COUNTER.next_serial(),
-        );
+        move_grab::handle_move_request(self, window, seat, SERIAL_COUNTER.next_serial());
+    }
+
+    fn maximize_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        match self.x11_window(&surface) {
+            Some(window) => self.maximize_window(&window),
+            None => reply_configure(&surface),
+        }
+    }
+
+    fn unmaximize_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        match self.x11_window(&surface) {
+            Some(window) => self.unmaximize_window(&window),
+            None => reply_configure(&surface),
+        }
+    }
+
+    fn fullscreen_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        match self.x11_window(&surface) {
+            Some(window) => self.fullscreen_window(&window, None),
+            None => reply_configure(&surface),
+        }
+    }
+
+    fn unfullscreen_request(&mut self, _xwm: XwmId, surface: X11Surface) {
+        match self.x11_window(&surface) {
+            Some(window) => self.unfullscreen_window(&window),
+            None => reply_configure(&surface),
+        }
+    }
+
+    fn property_notify(&mut self, _xwm: XwmId, surface: X11Surface, property: WmWindowProperty) {
+        // The rules only match on those
+        if !matches!(property, WmWindowProperty::Title | WmWindowProperty::Class) {
+            return;
+        }
+        let window = self
+            .space
+            .elements()
+            .cloned()
+            .chain(self.hidden_windows())
+            .chain(self.unmapped_x11_windows.iter().cloned())
+            .find(|w| w.x11_surface() == Some(&surface));
+        if let Some(window) = window {
+            self.apply_window_rules(&window);
+        }
+    }
+}
+
+impl ThingState {
+    /// The window of `surface`, if it is displayed
+    fn x11_window(&self, surface: &X11Surface) -> Option<Window> {
+        self.space
+            .elements()
+            .find(|w| w.x11_surface() == Some(surface))
+            .cloned()
+    }
+}
+
+/// Confirms the current geometry of `surface`, when one of its requests cannot be honoured
+fn reply_configure(surface: &X11Surface) {
+    if let Err(err) = surface.configure(None) {
+        error!(?err, "Unable to configure X11 window");
     }
 }
 
@@ -256,6 +400,17 @@ impl XWaylandShellHandler for ThingState {
     fn xwayland_shell_state(&mut self) -> &mut XWaylandShellState {
         &mut self.xw_shell_state
     }
+
+    fn surface_associated(&mut self, _xwm: XwmId, _wl_surface: WlSurface, surface: X11Surface) {
+        // Windows are usually mapped before their wayland surface is known, and could not be
+        // focused then
+        if surface.is_override_redirect() {
+            return;
+        }
+        if let Some(window) = self.x11_window(&surface) {
+            self.focus_window(&window, SERIAL_COUNTER.next_serial());
+        }
+    }
 }
 
 delegate_xwayland_shell!(ThingState);
@@ -21,7 +21,7 @@ use smithay::{
     reexports::{
         calloop::{
             channel::{Channel, Event},
-            timer::{TimeoutAction, Timer},
+            ping::make_ping,
             EventLoop, LoopSignal,
         },
         wayland_server::{Client, Display, DisplayHandle, Resource},
@@ -30,8 +30,8 @@ use smithay::{
     wayland::seat::WaylandFocus,
 };
 use wayland_compositor_thing::{
-    backend::CalloopData,
-    state::{ClientState, ThingState},
+    backend::{scheduler::submit_estimated_frame, CalloopData},
+    state::{ClientState, StateOptions, ThingState},
 };
 
 use crate::WlcsEvent;
@@ -40,7 +40,12 @@ pub fn run(channel: Channel<WlcsEvent>) {
     let display = Display::new().expect("Unable to create display");
     let dh = display.handle();
     let mut event_loop = EventLoop::try_new().expect("Unable to create event loop");
-    let mut state = ThingState::new(event_loop.handle(), display);
+    let mut state = ThingState::new(
+        event_loop.handle(),
+        event_loop.get_signal(),
+        display,
+        StateOptions::default(),
+    );
 
     let clients: Arc<Mutex<HashMap<i32, Client>>> = Arc::new(Mutex::new(HashMap::new()));
 
@@ -63,7 +68,7 @@ pub fn run(channel: Channel<WlcsEvent>) {
     let _ = output.create_global::<ThingState>(&dh);
     output.change_current_state(
         Some(mode),
-        Some(Transform::Flipped180),
+        Some(Transform::Normal),
         None,
         Some((0, 0).into()),
     );
@@ -77,10 +82,18 @@ pub fn run(channel: Channel<WlcsEvent>) {
 
     std::env::set_var("WAYLAND_DISPLAY", &state.socket_name);
 
+    let (ping, ping_source) = make_ping().expect("Unable to create render ping");
+    state.render_scheduler.set_waker(ping);
+    state.render_scheduler.queue_redraw(&output);
+
     let loop_signal = event_loop.get_signal();
     let _ = event_loop
         .handle()
-        .insert_source(Timer::immediate(), move |_instant, _, data| {
+        .insert_source(ping_source, move |_, _, data| {
+            if !data.state.render_scheduler.should_render(&output) {
+                return;
+            }
+            submit_estimated_frame(&mut data.state, &output);
             render(
                 &mut data.dh,
                 &mut data.state,
@@ -88,8 +101,6 @@ pub fn run(channel: Channel<WlcsEvent>) {
                 &output,
                 &mut damage_tracker,
             );
-            // Draw 60 time a second
-            TimeoutAction::ToDuration(Duration::from_millis(16))
         });
 
     event_loop
@@ -102,7 +113,9 @@ pub fn run(channel: Channel<WlcsEvent>) {
 
     let mut data = CalloopData { state, dh };
     event_loop
-        .run(None, &mut data, move |_| {})
+        .run(None, &mut data, move |data| {
+            let _ = data.dh.flush_clients();
+        })
         .expect("Unable to start event loop");
 }
 
@@ -144,6 +157,7 @@ fn handle_event(
             if let Some(toplevel) = toplevel.cloned() {
                 // set its location
                 data.state.space.map_element(toplevel, location, false);
+                data.state.queue_redraw_all();
             }
         }
 
@@ -240,14 +254,7 @@ fn render(
         [0.0, 0.0, 0.0, 1.0],
     );
 
-    state.space.elements().for_each(|window| {
-        window.send_frame(
-            &output,
-            state.start_time.elapsed(),
-            Some(Duration::ZERO),
-            |_, _| Some(output.clone()),
-        )
-    });
+    state.send_frames(output);
 
     state.space.refresh();
     state.popup_manager.cleanup();